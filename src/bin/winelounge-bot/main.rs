@@ -0,0 +1,127 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+
+use winelounge::connection::Connection;
+use winelounge::level::Level;
+use winelounge::recording::Recorder;
+use winelounge::transport::NetworkConditions;
+use winelounge::world::{World, TIMESTEP};
+
+mod strategy;
+
+/// Environment variable containing the password required to join the server
+const SERVER_PASSWORD_VARIABLE: &str = "WINELOUNGE_SERVER_PASSWORD";
+
+fn main() {
+    simple_logger::SimpleLogger::new().env().init().unwrap();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let address = arg_value(&args, "--connect").map_or("127.0.0.1:7888", String::as_str);
+    let bots = arg_value(&args, "--bots").map_or(1, |bots| {
+        bots.parse::<usize>().expect("Invalid number of bots")
+    });
+
+    let mut handshake = vec![];
+    if let Ok(password) = std::env::var(SERVER_PASSWORD_VARIABLE) {
+        handshake.push(format!("Password {}", password));
+    }
+    if let Some(room) = arg_value(&args, "--room") {
+        handshake.push(format!("Room {}", room));
+    }
+    let fingerprint = arg_value(&args, "--tls-fingerprint").cloned();
+    let conditions = arg_value(&args, "--simulate-network").map(|conditions| {
+        conditions
+            .parse::<NetworkConditions>()
+            .expect("Invalid network conditions")
+    });
+
+    let level = match arg_value(&args, "--level").map(|file| Level::load(file)) {
+        Some(Ok(level)) => level,
+        Some(Err(e)) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        None => Level::default(),
+    };
+
+    let recorder = arg_value(&args, "--record")
+        .map(|file| Recorder::create(file).expect("Cannot create recording"));
+
+    let handles = (0..bots)
+        .map(|index| {
+            let address = address.to_string();
+            let fingerprint = fingerprint.clone();
+            let conditions = conditions.clone();
+            let recording = recorder.clone().map(|recorder| (recorder, index as u64));
+            let handshake = handshake.clone();
+            let level = level.clone();
+            thread::spawn(move || {
+                match Connection::connect(
+                    &address,
+                    fingerprint.as_deref(),
+                    conditions,
+                    recording,
+                    handshake,
+                    &level,
+                ) {
+                    Ok(connection) => play(connection, &level),
+                    Err(e) => error!("{}", e),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        let _r = handle.join();
+    }
+}
+
+/// Plays until the server closes the connection
+fn play(mut connection: Connection, level: &Level) {
+    info!("Bot {} joined", connection.player_id);
+    let mut world = World::init();
+    world.set_level(level);
+    world.set_player_id(&connection.player_id);
+    world.follow_server(true);
+
+    let mut updated_at = Instant::now();
+    let mut accumulator = Duration::ZERO;
+    loop {
+        while let Some(line) = connection.try_recv() {
+            if let Ok(command) = line.parse() {
+                world.execute_command(command);
+            }
+        }
+        if connection.is_closed() {
+            info!(
+                "Bot {} left with {} points",
+                connection.player_id,
+                world.player().points
+            );
+            return;
+        }
+
+        // Moving players walk as far as within the time passed, in the same steps as for other clients
+        let now = Instant::now();
+        accumulator += now - updated_at;
+        updated_at = now;
+        while accumulator >= TIMESTEP {
+            world.update(TIMESTEP);
+            accumulator -= TIMESTEP;
+        }
+        strategy::step(&mut world);
+        for command in world.take_outgoing() {
+            connection.send(command.to_string());
+        }
+
+        thread::sleep(strategy::STEP_INTERVAL);
+    }
+}
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+}
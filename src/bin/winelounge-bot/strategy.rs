@@ -0,0 +1,180 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use winelounge::geometry::{Point, Rect};
+use winelounge::net::{BoxAreaContent, Direction};
+use winelounge::player::SPEED;
+use winelounge::world::{BoxArea, World};
+
+/// Time between two decisions of a bot where to head for
+pub const STEP_INTERVAL: Duration = Duration::from_millis(150);
+
+/// Distance between positions considered when searching a path, as far as a player walks
+/// between two decisions
+fn step_distance() -> i32 {
+    (SPEED * STEP_INTERVAL.as_secs_f64()) as i32
+}
+
+/// Heads for the current goal on the shortest path, the way a player holding keys would.
+///
+/// The player keeps moving until the direction changes or the goal is reached.
+/// Moving and handling collisions with items is up to the world being updated.
+pub fn step(world: &mut World) {
+    let target = target(world);
+    match first_step(world, &target) {
+        Some(direction) if world.player().moving() == [direction.clone()] => {}
+        Some(direction) => {
+            world.stop_player();
+            world.start_moving(direction);
+        }
+        None => world.stop_player(),
+    }
+}
+
+/// Selects where to go: drink filled glasses, fill empty glasses, pick glasses or open hidden boxes.
+///
+/// Without anything to do, the bot waits in the nearest lounge.
+fn target(world: &World) -> Rect {
+    let player = world.player();
+    let can_pick_glass = player.can_pick_glass(world.rules().glass_space);
+    let wanted = if player.can_drink_glass() && (!player.can_fill_glass() || !can_pick_glass) {
+        vec![]
+    } else if player.can_fill_glass() {
+        vec![BoxAreaContent::FilledBottle, BoxAreaContent::EmptyGlass]
+    } else {
+        vec![BoxAreaContent::EmptyGlass, BoxAreaContent::HiddenBox]
+    };
+
+    wanted
+        .iter()
+        .filter(|content| **content != BoxAreaContent::EmptyGlass || can_pick_glass)
+        .find_map(|content| {
+            world
+                .box_areas()
+                .iter()
+                .filter(|box_area| &box_area.content == content)
+                .map(|box_area| reachable_area(world, box_area))
+                .min_by_key(|area| distance(player.center(), area.center()))
+        })
+        .or_else(|| {
+            world
+                .lounge_rects()
+                .into_iter()
+                .min_by_key(|lounge| distance(player.center(), lounge.center()))
+        })
+        .unwrap_or(player.bounding_rect())
+}
+
+fn moved(position: Point, direction: &Direction) -> Point {
+    let step = step_distance();
+    match direction {
+        Direction::Up => Point::new(position.x, position.y - step),
+        Direction::Down => Point::new(position.x, position.y + step),
+        Direction::Left => Point::new(position.x - step, position.y),
+        Direction::Right => Point::new(position.x + step, position.y),
+    }
+}
+
+/// Searches the shortest path of steps until the center of the player is within the target area.
+///
+/// Returns the first step of this path, or nothing if the target is reached or cannot be reached.
+fn first_step(world: &World, target: &Rect) -> Option<Direction> {
+    let player = world.player();
+    let position = player.position();
+    if target.contains_point(player.center()) {
+        return None;
+    }
+
+    let mut visited = HashSet::from([(position.x, position.y)]);
+    let mut queue = VecDeque::from([(position, None)]);
+    while let Some((position, first)) = queue.pop_front() {
+        for direction in [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ] {
+            let next = moved(position, &direction);
+            if world.can_stand_at(next) && visited.insert((next.x, next.y)) {
+                let first = first.clone().unwrap_or(direction);
+                if target.contains_point(player.at(next).center()) {
+                    return Some(first);
+                }
+                queue.push_back((next, Some(first)));
+            }
+        }
+    }
+    None
+}
+
+fn distance(a: Point, b: Point) -> i32 {
+    (a.x - b.x).abs() + (a.y - b.y).abs()
+}
+
+/// Part of a box area the center of the player can reach without leaving the playable area
+fn reachable_area(world: &World, box_area: &BoxArea) -> Rect {
+    let area = box_area.bounding_rect();
+    let playable = world.playable_rect();
+    let (width, height) = world.player().bounding_rect().size();
+    let (width, height) = (width as i32, height as i32);
+    let min = Point::new(playable.x + 1 + width / 2, playable.y + 1 + height / 2);
+    let max = Point::new(
+        playable.width as i32 - width - 1 + width / 2,
+        playable.bottom() - height - 1 + height / 2,
+    );
+    let x = area.x.max(min.x);
+    let y = area.y.max(min.y);
+    Rect::new(
+        x,
+        y,
+        (area.right().min(max.x + 1) - x) as u32,
+        (area.bottom().min(max.y + 1) - y) as u32,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use winelounge::clock::ManualClock;
+    use winelounge::net::{BoxAreaContent, Command};
+    use winelounge::world::World;
+
+    use crate::strategy::{step, STEP_INTERVAL};
+
+    fn play(world: &mut World, steps: usize) -> Vec<Command> {
+        (0..steps)
+            .flat_map(|_| {
+                step(world);
+                world.update(STEP_INTERVAL);
+                world.take_outgoing()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_pick_glass_fill_it_and_drink_it_in_lounge() {
+        // Box areas are refilled at random as world time passes, a fixed seed keeps the test stable
+        let mut world = World::new(ManualClock::starting_at(1_700_000_000_000), 1);
+        world.set_player_id("bot");
+        world.execute_command(Command::UpdateBoxArea(
+            "RightBottom".to_string(),
+            BoxAreaContent::Nothing,
+        ));
+        world.execute_command(Command::UpdateBoxArea(
+            "LeftBottom".to_string(),
+            BoxAreaContent::FilledBottle,
+        ));
+
+        let commands = play(&mut world, 200);
+
+        assert!(commands.contains(&Command::UpdateBoxArea(
+            "RightTop".to_string(),
+            BoxAreaContent::Nothing
+        )));
+        assert!(commands.contains(&Command::UpdateBoxArea(
+            "LeftBottom".to_string(),
+            BoxAreaContent::EmptyBottle
+        )));
+        assert!(commands.contains(&Command::UpdateInventory("bot".to_string(), 0, 0, 10)));
+        assert_eq!(10, world.player().points);
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::{interval, sleep_until};
+
+use winelounge::level::Level;
+use winelounge::net::{Command, Direction};
+use winelounge::protocol::Message;
+use winelounge::world::{World, TIMESTEP};
+
+/// Environment variable containing the password required to join the server
+const SERVER_PASSWORD_VARIABLE: &str = "WINELOUNGE_SERVER_PASSWORD";
+
+/// Prefix of chat messages used to measure broadcast latency
+const PROBE_PREFIX: &str = "probe:";
+
+/// Time between two latency probes sent by each client
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Port the server serves metrics on, if no other metrics address is given using `--metrics`
+const METRICS_PORT: u16 = 9888;
+
+/// Results of one or more simulated clients
+#[derive(Debug, Default, PartialEq)]
+struct Stats {
+    clients: usize,
+    connect_errors: usize,
+    rejects: usize,
+    disconnects: usize,
+    sent: usize,
+    received: usize,
+    /// Broadcast latencies of probes in microseconds
+    latencies: Vec<u64>,
+}
+
+impl Stats {
+    fn merge(&mut self, other: Stats) {
+        self.clients += other.clients;
+        self.connect_errors += other.connect_errors;
+        self.rejects += other.rejects;
+        self.disconnects += other.disconnects;
+        self.sent += other.sent;
+        self.received += other.received;
+        self.latencies.extend(other.latencies);
+    }
+
+    /// Returns the latency in milliseconds not exceeded by given percentage of probes
+    fn percentile(&self, percent: usize) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+        let index = (latencies.len() * percent).div_ceil(100).max(1) - 1;
+        latencies[index] as f64 / 1000.0
+    }
+
+    fn report(&self, duration: Duration) -> String {
+        let seconds = duration.as_secs_f64();
+        format!(
+            "Clients:      {} joined, {} connect errors, {} rejected, {} disconnected\n\
+             Sent:         {} commands ({:.1}/s)\n\
+             Received:     {} commands ({:.1}/s)\n\
+             Latency (ms): p50 {:.2}, p90 {:.2}, p99 {:.2}, max {:.2} ({} probes)",
+            self.clients,
+            self.connect_errors,
+            self.rejects,
+            self.disconnects,
+            self.sent,
+            self.sent as f64 / seconds,
+            self.received,
+            self.received as f64 / seconds,
+            self.percentile(50),
+            self.percentile(90),
+            self.percentile(99),
+            self.percentile(100),
+            self.latencies.len(),
+        )
+    }
+}
+
+/// Player simulated by a client, moving around like a human would using the arrow keys.
+///
+/// The player walks through its own world of the level, so positions, box areas and inventories
+/// sent are the ones the client would send.
+struct Player {
+    world: World,
+    direction: Direction,
+    updated_at: Instant,
+    accumulator: Duration,
+}
+
+impl Player {
+    fn new(id: &str, level: &Level) -> Player {
+        let mut world = World::init();
+        world.set_level(level);
+        world.set_player_id(id);
+        world.follow_server(true);
+        Player {
+            world,
+            direction: Direction::Down,
+            updated_at: Instant::now(),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    /// Walks as far as within the time passed, in the same steps as the client does
+    fn update(&mut self) {
+        let now = Instant::now();
+        self.accumulator += now - self.updated_at;
+        self.updated_at = now;
+        while self.accumulator >= TIMESTEP {
+            self.world.update(TIMESTEP);
+            self.accumulator -= TIMESTEP;
+        }
+    }
+
+    /// Executes a line received from the server
+    fn execute(&mut self, line: &str) {
+        if let Ok(command) = line.parse() {
+            self.update();
+            self.world.execute_command(command);
+        }
+    }
+
+    /// Returns commands for the next short key press: releasing the key held and pressing it again,
+    /// now and then or when blocked turning into another direction.
+    /// Walking over box areas and lounges picks, fills and drinks glasses like the client does.
+    fn next_commands(&mut self) -> Vec<Command> {
+        let position = self.world.player().position();
+        self.update();
+        let blocked = self.world.player().position() == position;
+        self.world.stop_moving(&self.direction);
+        if blocked || rand::random::<u8>().is_multiple_of(4) {
+            self.direction = match rand::random::<u8>() % 4 {
+                0 => Direction::Up,
+                1 => Direction::Down,
+                2 => Direction::Left,
+                _ => Direction::Right,
+            };
+        }
+        self.world.start_moving(self.direction.clone());
+        self.world.take_outgoing()
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    simple_logger::SimpleLogger::new().env().init().unwrap();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let address = arg_value(&args, "--connect").map_or("127.0.0.1:7888", String::as_str);
+    let clients = arg_number(&args, "--clients", 10);
+    let duration = Duration::from_secs(arg_number(&args, "--duration", 30) as u64);
+    let rate = arg_number(&args, "--rate", 7).max(1);
+    let rooms = arg_value(&args, "--rooms")
+        .map(|rooms| rooms.split(',').map(str::to_string).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let level = match arg_value(&args, "--level").map(|file| Level::load(file)) {
+        Some(Ok(level)) => Arc::new(level),
+        Some(Err(e)) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        None => Arc::new(Level::default()),
+    };
+    let metrics = arg_value(&args, "--metrics").cloned().unwrap_or_else(|| {
+        let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+        format!("{}:{}", host, METRICS_PORT)
+    });
+    let errors_before = match server_errors(&metrics).await {
+        Ok(errors) => Some(errors),
+        Err(e) => {
+            warn!("{}, not reporting server errors", e);
+            None
+        }
+    };
+
+    info!(
+        "Starting {} clients sending {} moves per second for {} s",
+        clients,
+        rate,
+        duration.as_secs()
+    );
+    let started_at = Instant::now();
+    let deadline = tokio::time::Instant::from_std(started_at + duration);
+
+    let mut tasks = vec![];
+    for index in 0..clients {
+        let mut handshake = vec![];
+        if let Ok(password) = std::env::var(SERVER_PASSWORD_VARIABLE) {
+            handshake.push(format!("Password {}", password));
+        }
+        if !rooms.is_empty() {
+            handshake.push(format!("Room {}", rooms[index % rooms.len()]));
+        }
+        tasks.push(tokio::spawn(run_client(
+            address.to_string(),
+            handshake,
+            level.clone(),
+            started_at,
+            deadline,
+            rate,
+        )));
+        // Do not overwhelm the accept loop, connecting players arrive one by one
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let mut stats = Stats::default();
+    for task in tasks {
+        match task.await {
+            Ok(client_stats) => stats.merge(client_stats),
+            Err(e) => error!("Client failed: {}", e),
+        }
+    }
+    println!("{}", stats.report(duration));
+
+    if let Some(before) = errors_before {
+        match server_errors(&metrics).await {
+            Ok(after) => {
+                for (series, count) in after {
+                    let count = count.saturating_sub(before.get(&series).copied().unwrap_or(0));
+                    println!("Server:       {} {}", series, count);
+                }
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+/// Joins the game and plays until the deadline, returning what happened
+async fn run_client(
+    address: String,
+    handshake: Vec<String>,
+    level: Arc<Level>,
+    started_at: Instant,
+    deadline: tokio::time::Instant,
+    rate: usize,
+) -> Stats {
+    let mut stats = Stats::default();
+    let socket = match TcpStream::connect(&address).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("Cannot connect to {}: {}", address, e);
+            stats.connect_errors += 1;
+            return stats;
+        }
+    };
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let mut join = handshake.join("\n");
+    if !join.is_empty() {
+        join.push('\n');
+    }
+    join.push_str("Join\n");
+    if writer.write_all(join.as_bytes()).await.is_err() {
+        stats.disconnects += 1;
+        return stats;
+    }
+    let player_id = match lines.next_line().await {
+        Ok(Some(line)) => match line.parse::<Message>() {
+            Ok(Message::Welcome(player_id, _, level_hash)) if level_hash == level.hash() => {
+                player_id
+            }
+            _ => {
+                warn!("{}", line);
+                stats.rejects += 1;
+                return stats;
+            }
+        },
+        _ => {
+            stats.disconnects += 1;
+            return stats;
+        }
+    };
+    stats.clients += 1;
+
+    let mut player = Player::new(&player_id, &level);
+    let mut moves = interval(Duration::from_secs(1) / rate as u32);
+    let mut probes = interval(PROBE_INTERVAL);
+    let deadline = sleep_until(deadline);
+    tokio::pin!(deadline);
+
+    loop {
+        let outgoing = tokio::select! {
+            _ = &mut deadline => break,
+            _ = moves.tick() => player
+                .next_commands()
+                .iter()
+                .map(|command| format!("{}\n", command))
+                .collect::<Vec<_>>(),
+            _ = probes.tick() => vec![format!(
+                "Chat {} {}{}\n",
+                player_id,
+                PROBE_PREFIX,
+                started_at.elapsed().as_micros()
+            )],
+            line = lines.next_line() => {
+                match line {
+                    Ok(Some(line)) => {
+                        stats.received += 1;
+                        handle_line(&line, &mut player, started_at, &mut stats);
+                    }
+                    _ => {
+                        stats.disconnects += 1;
+                        break;
+                    }
+                }
+                continue;
+            }
+        };
+        stats.sent += outgoing.len();
+        if writer
+            .write_all(outgoing.concat().as_bytes())
+            .await
+            .is_err()
+        {
+            stats.disconnects += 1;
+            break;
+        }
+    }
+    stats
+}
+
+/// Executes lines of the server in the world of the player and measures latency of probes
+/// of other clients
+fn handle_line(line: &str, player: &mut Player, started_at: Instant, stats: &mut Stats) {
+    match line.split(' ').collect::<Vec<_>>().as_slice() {
+        ["Chat", _, text] => {
+            if let Some(Ok(sent_at)) = text
+                .strip_prefix(PROBE_PREFIX)
+                .map(|micros| micros.parse::<u64>())
+            {
+                let now = started_at.elapsed().as_micros() as u64;
+                stats.latencies.push(now.saturating_sub(sent_at));
+            }
+        }
+        _ => player.execute(line),
+    }
+}
+
+/// Reads the error counters of the server from its metrics endpoint
+async fn server_errors(address: &str) -> Result<BTreeMap<String, u64>, String> {
+    let mut socket = TcpStream::connect(address)
+        .await
+        .map_err(|e| format!("Cannot connect to {}: {}", address, e))?;
+    socket
+        .write_all(format!("GET /metrics HTTP/1.1\r\nHost: {}\r\n\r\n", address).as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    socket
+        .read_to_string(&mut response)
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.split_once("\r\n\r\n") {
+        Some((head, body)) if head.starts_with("HTTP/1.1 200") => Ok(error_counters(body)),
+        _ => Err(format!("Cannot read metrics of {}", address)),
+    }
+}
+
+/// Returns the values of all error counters in metrics of Prometheus text format
+fn error_counters(metrics: &str) -> BTreeMap<String, u64> {
+    metrics
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.rsplit_once(' '))
+        .filter(|(series, _)| {
+            series
+                .split('{')
+                .next()
+                .is_some_and(|name| name.ends_with("_errors_total"))
+        })
+        .filter_map(|(series, value)| Some((series.to_string(), value.parse::<f64>().ok()? as u64)))
+        .collect()
+}
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+}
+
+fn arg_number(args: &[String], name: &str, default: usize) -> usize {
+    arg_value(args, name).map_or(default, |value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid value for {}: {}", name, value))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error_counters, Stats};
+
+    #[test]
+    fn should_merge_stats_and_calculate_percentiles() {
+        let mut stats = Stats {
+            clients: 1,
+            sent: 10,
+            latencies: vec![4000, 1000, 3000],
+            ..Stats::default()
+        };
+        stats.merge(Stats {
+            clients: 1,
+            rejects: 1,
+            received: 5,
+            latencies: vec![2000],
+            ..Stats::default()
+        });
+
+        assert_eq!(2, stats.clients);
+        assert_eq!(1, stats.rejects);
+        assert_eq!(10, stats.sent);
+        assert_eq!(5, stats.received);
+        assert_eq!(2.0, stats.percentile(50));
+        assert_eq!(4.0, stats.percentile(90));
+        assert_eq!(4.0, stats.percentile(100));
+    }
+
+    #[test]
+    fn should_read_error_counters_of_metrics() {
+        let metrics = "# HELP winelounge_parse_errors_total Lines received from clients that could not be parsed.\n\
+            # TYPE winelounge_parse_errors_total counter\n\
+            winelounge_parse_errors_total 3\n\
+            winelounge_commands_received_total{type=\"Move\"} 12\n\
+            winelounge_io_errors_total{kind=\"write\"} 1\n";
+
+        assert_eq!(
+            vec![
+                ("winelounge_io_errors_total{kind=\"write\"}".to_string(), 1),
+                ("winelounge_parse_errors_total".to_string(), 3),
+            ],
+            error_counters(metrics).into_iter().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{error, info, warn};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::{sleep_until, Instant};
+
+use winelounge::clock::ManualClock;
+use winelounge::level::Level;
+use winelounge::net::Command;
+use winelounge::protocol::Message;
+use winelounge::recording::Entry;
+use winelounge::world::{World, TIMESTEP};
+
+/// Environment variable containing the password required to join the server
+const SERVER_PASSWORD_VARIABLE: &str = "WINELOUNGE_SERVER_PASSWORD";
+
+/// Environment variable containing the password of the player to login with
+const PLAYER_PASSWORD_VARIABLE: &str = "WINELOUNGE_PLAYER_PASSWORD";
+
+/// Time to wait for lines from the server after the last recorded line of a connection
+const GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Start time and seed of the world replaying a connection, so replays behave the same every time
+const WORLD_STARTED_AT: i64 = 1_700_000_000_000;
+const WORLD_SEED: u64 = 0;
+
+/// Lines of one connection as seen by the client
+#[derive(Debug, Default)]
+struct Conversation {
+    /// Entries in recorded order, received if sent by the server
+    entries: Vec<Entry>,
+}
+
+impl Conversation {
+    /// Returns lines sent by the server
+    fn received(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.received)
+            .map(|entry| entry.line.clone())
+            .collect()
+    }
+}
+
+/// Parses a recording, one entry per line
+fn parse(content: &str) -> Result<Vec<Entry>, String> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| {
+            line.parse::<Entry>()
+                .map_err(|_| format!("Invalid entry in line {}: {}", index + 1, line))
+        })
+        .collect()
+}
+
+/// Groups entries by connection, using the perspective of the client.
+///
+/// A recording of the server has received what the client has sent, this is detected
+/// by the client's `Join` being received.
+fn conversations(entries: &[Entry]) -> BTreeMap<u64, Conversation> {
+    let server_recording = entries
+        .iter()
+        .any(|entry| entry.received && entry.line == "Join");
+    let mut conversations = BTreeMap::<u64, Conversation>::new();
+    for entry in entries {
+        conversations
+            .entry(entry.connection)
+            .or_default()
+            .entries
+            .push(Entry {
+                received: entry.received != server_recording,
+                ..entry.clone()
+            });
+    }
+    conversations
+}
+
+/// Replaces passwords, masked when recorded, by the ones of the environment
+fn unmask(line: &str) -> String {
+    match line.split(' ').collect::<Vec<_>>().as_slice() {
+        ["Password", "***"] => match std::env::var(SERVER_PASSWORD_VARIABLE) {
+            Ok(password) => format!("Password {}", password),
+            Err(_) => line.to_string(),
+        },
+        ["Login", name, "***"] => match std::env::var(PLAYER_PASSWORD_VARIABLE) {
+            Ok(password) => format!("Login {} {}", name, password),
+            Err(_) => line.to_string(),
+        },
+        _ => line.to_string(),
+    }
+}
+
+/// Replaces recorded player ids and tokens by the ones assigned by the server when replaying.
+///
+/// Only the id and token fields of messages are replaced, chat texts or box area ids are kept
+/// even if they look like an id. Lines that are no messages are kept as they are.
+fn substitute(line: &str, ids: &HashMap<String, String>) -> String {
+    let id = |id: String| ids.get(&id).cloned().unwrap_or(id);
+    let message = match line.parse::<Message>() {
+        Ok(Message::Join(Some(token))) => Message::Join(Some(id(token))),
+        Ok(Message::Welcome(player_id, token, level_hash)) => {
+            Message::Welcome(id(player_id), id(token), level_hash)
+        }
+        Ok(Message::Spawn(player_id, x, y)) => Message::Spawn(id(player_id), x, y),
+        Ok(Message::Face(player_id, direction)) => Message::Face(id(player_id), direction),
+        Ok(Message::Move(player_id, direction)) => Message::Move(id(player_id), direction),
+        Ok(Message::Stop(player_id, x, y)) => Message::Stop(id(player_id), x, y),
+        Ok(Message::Inventory(player_id, empty_glasses, filled_glasses, points)) => {
+            Message::Inventory(id(player_id), empty_glasses, filled_glasses, points)
+        }
+        Ok(Message::Remove(player_id)) => Message::Remove(id(player_id)),
+        Ok(Message::Name(player_id, name)) => Message::Name(id(player_id), name),
+        Ok(Message::Chat(player_id, text)) => Message::Chat(id(player_id), text),
+        _ => return line.to_string(),
+    };
+    message.to_string()
+}
+
+/// Returns lines expected but not received and lines received but not expected, ignoring order
+fn diverging(expected: &[String], received: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut unexpected = received.to_vec();
+    let missing = expected
+        .iter()
+        .filter(
+            |line| match unexpected.iter().position(|other| other == *line) {
+                Some(index) => {
+                    unexpected.remove(index);
+                    false
+                }
+                None => true,
+            },
+        )
+        .cloned()
+        .collect();
+    (missing, unexpected)
+}
+
+#[tokio::main]
+async fn main() {
+    simple_logger::SimpleLogger::new().env().init().unwrap();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let file = match args.get(1) {
+        Some(file) if !file.starts_with("--") => file,
+        _ => {
+            eprintln!("Usage: winelounge-replay <recording> [--connect <address>] [--speed <factor>] [--world] [--connection <id>] [--level <file>]");
+            std::process::exit(2);
+        }
+    };
+    let content = std::fs::read_to_string(file).unwrap_or_else(|e| {
+        eprintln!("Cannot read {}: {}", file, e);
+        std::process::exit(2);
+    });
+    let entries = parse(&content).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    let conversations = conversations(&entries);
+
+    let divergences = if args.iter().any(|arg| arg == "--world") {
+        let connection = arg_value(&args, "--connection").map_or_else(
+            || conversations.keys().next().copied().unwrap_or_default(),
+            |connection| connection.parse().expect("Invalid connection id"),
+        );
+        let level = match arg_value(&args, "--level").map(|file| Level::load(file)) {
+            Some(Ok(level)) => level,
+            Some(Err(e)) => {
+                error!("{}", e);
+                std::process::exit(2);
+            }
+            None => Level::default(),
+        };
+        match conversations.get(&connection) {
+            Some(conversation) => replay_world(conversation, &level),
+            None => vec![format!("No connection {} recorded", connection)],
+        }
+    } else {
+        let address = arg_value(&args, "--connect").map_or("127.0.0.1:7888", String::as_str);
+        let speed = arg_value(&args, "--speed").map_or(1.0, |speed| {
+            speed.parse::<f64>().expect("Invalid replay speed")
+        });
+        replay_server(address, conversations, speed).await
+    };
+
+    for divergence in &divergences {
+        println!("{}", divergence);
+    }
+    println!(
+        "{} lines replayed, {} divergences",
+        entries.len(),
+        divergences.len()
+    );
+    if !divergences.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Replays lines sent by clients against a fresh server at their recorded times
+/// and compares what the server sent back with the recording.
+async fn replay_server(
+    address: &str,
+    conversations: BTreeMap<u64, Conversation>,
+    speed: f64,
+) -> Vec<String> {
+    let started_at = Instant::now();
+    let ids = Arc::new(Mutex::new(HashMap::new()));
+
+    let tasks = conversations
+        .into_iter()
+        .map(|(connection, conversation)| {
+            let address = address.to_string();
+            let ids = ids.clone();
+            let at = move |millis: u64| started_at + Duration::from_millis(millis).div_f64(speed);
+            tokio::spawn(async move {
+                let received = replay_connection(&address, &conversation, at, &ids).await;
+                (connection, conversation.received(), received)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut divergences = vec![];
+    for task in tasks {
+        let Ok((connection, expected, received)) = task.await else {
+            continue;
+        };
+        let ids = ids.lock().unwrap().clone();
+        let expected = expected
+            .iter()
+            .map(|line| substitute(line, &ids))
+            .collect::<Vec<_>>();
+        let (missing, unexpected) = diverging(&expected, &received);
+        divergences.extend(
+            missing
+                .iter()
+                .map(|line| format!("Connection {}: missing {}", connection, line)),
+        );
+        divergences.extend(
+            unexpected
+                .iter()
+                .map(|line| format!("Connection {}: unexpected {}", connection, line)),
+        );
+    }
+    divergences
+}
+
+/// Sends the recorded lines of a client and returns all lines received until shortly after the last recorded line
+async fn replay_connection(
+    address: &str,
+    conversation: &Conversation,
+    at: impl Fn(u64) -> Instant,
+    ids: &Mutex<HashMap<String, String>>,
+) -> Vec<String> {
+    let mut received = vec![];
+    let mut pending = conversation
+        .entries
+        .iter()
+        .filter(|entry| !entry.received)
+        .collect::<VecDeque<_>>();
+    let (Some(first), Some(last)) = (pending.front(), conversation.entries.last()) else {
+        return received;
+    };
+    let deadline = sleep_until(at(last.millis) + GRACE_PERIOD);
+    tokio::pin!(deadline);
+
+    sleep_until(at(first.millis)).await;
+    let socket = match TcpStream::connect(address).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("Cannot connect to {}: {}", address, e);
+            return received;
+        }
+    };
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let recorded_welcome = conversation
+        .entries
+        .iter()
+        .find(|entry| entry.received && entry.line.starts_with("Welcome "))
+        .map(|entry| &entry.line);
+
+    loop {
+        let next = pending.front().map(|entry| at(entry.millis));
+        tokio::select! {
+            _ = &mut deadline => break,
+            _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                if let Some(entry) = pending.pop_front() {
+                    let line = substitute(&unmask(&entry.line), &ids.lock().unwrap());
+                    if writer.write_all(format!("{}\n", line).as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => {
+                    if let (Some(recorded), ["Welcome", player_id, token, _level_hash]) = (
+                        recorded_welcome,
+                        line.split(' ').collect::<Vec<_>>().as_slice(),
+                    ) {
+                        if let ["Welcome", recorded_id, recorded_token, _level_hash] =
+                            recorded.split(' ').collect::<Vec<_>>().as_slice()
+                        {
+                            info!("Replaying player {} as {}", recorded_id, player_id);
+                            let mut ids = ids.lock().unwrap();
+                            ids.insert(recorded_id.to_string(), player_id.to_string());
+                            ids.insert(recorded_token.to_string(), token.to_string());
+                        }
+                    }
+                    received.push(line);
+                }
+                _ => break,
+            }
+        }
+    }
+    received
+}
+
+/// Replays one connection against a fresh world playing given level, as the client would have.
+///
+/// Lines received from the server are executed, moves sent by the client are executed like
+/// key presses and the world is updated as time passes between entries. Stops are executed as
+/// recorded, as the position a player stops at depends on frame timing.
+/// Anything else sent by the world must match the recording.
+fn replay_world(conversation: &Conversation, level: &Level) -> Vec<String> {
+    let mut world = World::new(ManualClock::starting_at(WORLD_STARTED_AT), WORLD_SEED);
+    world.set_level(level);
+    let mut divergences = vec![];
+    let mut pending = VecDeque::<String>::new();
+    let mut player_id = String::new();
+    let mut world_time = Duration::ZERO;
+
+    for entry in &conversation.entries {
+        while world_time + TIMESTEP <= Duration::from_millis(entry.millis) {
+            world.update(TIMESTEP);
+            world_time += TIMESTEP;
+        }
+        pending.extend(world.take_outgoing().iter().map(Command::to_string));
+
+        let line = &entry.line;
+        if entry.received {
+            match line.split(' ').collect::<Vec<_>>().as_slice() {
+                ["Welcome", id, _token, level_hash] => {
+                    player_id = id.to_string();
+                    world.set_player_id(id);
+                    world.follow_server(true);
+                    if *level_hash != level.hash() {
+                        divergences.push("Server played another level".to_string());
+                    }
+                }
+                _ => {
+                    if let Ok(command) = line.parse() {
+                        world.execute_command(command);
+                    }
+                }
+            }
+            continue;
+        }
+
+        if let Some(expected) = pending.pop_front() {
+            if expected == *line {
+                continue;
+            }
+            divergences.push(format!("World sent {}, recorded {}", expected, line));
+            pending.clear();
+        }
+        match line.parse::<Command>() {
+            Ok(Command::MovePlayer(id, direction)) if id == player_id => {
+                world.start_moving(direction)
+            }
+            Ok(command) => world.execute_command(command),
+            // Handshake lines do not change the world
+            Err(_) => continue,
+        }
+        pending.extend(world.take_outgoing().iter().map(Command::to_string));
+        // The line just replayed is the first one sent by the world
+        if pending.front() == Some(line) {
+            pending.pop_front();
+        }
+    }
+
+    divergences.extend(
+        pending
+            .iter()
+            .map(|line| format!("World sent {}, not recorded", line)),
+    );
+    divergences
+}
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{conversations, diverging, parse, substitute};
+
+    #[test]
+    fn should_map_ids_and_report_divergences() {
+        let entries = parse(
+            "0 1 < Join\n\
+             5 1 > Welcome 1234 0123456789abcdef 5eed\n\
+             5 1 > Spawn 1234 380 250\n\
+             \n\
+             150 1 < Move 1234 Up\n",
+        )
+        .unwrap();
+        let conversations = conversations(&entries);
+        assert_eq!(
+            vec!["Welcome 1234 0123456789abcdef 5eed", "Spawn 1234 380 250"],
+            conversations[&1].received()
+        );
+
+        let ids = HashMap::from([
+            ("1234".to_string(), "5678".to_string()),
+            (
+                "0123456789abcdef".to_string(),
+                "fedcba9876543210".to_string(),
+            ),
+        ]);
+        let expected = conversations[&1]
+            .received()
+            .iter()
+            .map(|line| substitute(line, &ids))
+            .collect::<Vec<_>>();
+        let received = vec![
+            "Spawn 5678 380 250".to_string(),
+            "Welcome 5678 fedcba9876543210 5eed".to_string(),
+            "Spawn 5678 380 250".to_string(),
+        ];
+
+        assert_eq!(
+            (vec![], vec!["Spawn 5678 380 250".to_string()]),
+            diverging(&expected, &received)
+        );
+        assert!(parse("0 1 Join").is_err());
+    }
+
+    #[test]
+    fn should_substitute_ids_only() {
+        let ids = HashMap::from([("1234".to_string(), "5678".to_string())]);
+
+        assert_eq!(
+            "Chat 5678 1234 is my lucky number",
+            substitute("Chat 1234 1234 is my lucky number", &ids)
+        );
+        assert_eq!(
+            "UpdateBoxArea 1234 EmptyGlass",
+            substitute("UpdateBoxArea 1234 EmptyGlass", &ids)
+        );
+        assert_eq!("Login 1234 ***", substitute("Login 1234 ***", &ids));
+    }
+}
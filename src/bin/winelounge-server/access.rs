@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use ipnet::IpNet;
+
+/// File containing address rules and banned player names
+pub const ACCESS_FILE: &str = "winelounge.access";
+
+/// Address rules and banned player names, one rule per line:
+/// `allow <address range>`, `deny <address range>` or `ban <player name>`.
+///
+/// If there are any allow rules, only matching addresses are allowed.
+/// Deny rules take precedence over allow rules.
+#[derive(Debug, Default, PartialEq)]
+pub struct AccessRules {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+    banned: BTreeSet<String>,
+}
+
+impl AccessRules {
+    /// Checks if a client with given address may connect
+    pub fn is_address_allowed(&self, address: IpAddr) -> bool {
+        if self.deny.iter().any(|range| range.contains(&address)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|range| range.contains(&address))
+    }
+
+    /// Checks if a player name is banned
+    pub fn is_banned(&self, name: &str) -> bool {
+        self.banned.contains(name)
+    }
+
+    /// Bans a player name or denies an address or address range
+    pub fn ban(&mut self, target: &str) -> bool {
+        match parse_range(target) {
+            Some(range) if self.deny.contains(&range) => false,
+            Some(range) => {
+                self.deny.push(range);
+                true
+            }
+            None => self.banned.insert(target.to_string()),
+        }
+    }
+
+    /// Removes the ban of a player name or an address or address range
+    pub fn unban(&mut self, target: &str) -> bool {
+        match parse_range(target) {
+            Some(range) => {
+                let count = self.deny.len();
+                self.deny.retain(|denied| *denied != range);
+                self.deny.len() != count
+            }
+            None => self.banned.remove(target),
+        }
+    }
+}
+
+/// Parses an address range like `10.0.0.0/8` or a single address
+fn parse_range(s: &str) -> Option<IpNet> {
+    s.parse::<IpNet>()
+        .ok()
+        .or_else(|| s.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+impl Display for AccessRules {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for range in &self.allow {
+            writeln!(f, "allow {}", range)?;
+        }
+        for range in &self.deny {
+            writeln!(f, "deny {}", range)?;
+        }
+        for name in &self.banned {
+            writeln!(f, "ban {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for AccessRules {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = AccessRules::default();
+
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match line.split_once(' ') {
+                Some(("allow", range)) => rules.allow.push(parse_range(range.trim()).ok_or(())?),
+                Some(("deny", range)) => rules.deny.push(parse_range(range.trim()).ok_or(())?),
+                Some(("ban", name)) => {
+                    rules.banned.insert(name.trim().to_string());
+                }
+                _ => return Err(()),
+            }
+        }
+
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::access::AccessRules;
+
+    #[test]
+    fn should_check_address_rules() {
+        let rules = "allow 10.0.0.0/8\ndeny 10.1.0.0/16\n"
+            .parse::<AccessRules>()
+            .unwrap();
+
+        assert!(rules.is_address_allowed("10.2.3.4".parse().unwrap()));
+        assert!(!rules.is_address_allowed("10.1.2.3".parse().unwrap()));
+        assert!(!rules.is_address_allowed("192.168.1.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn should_allow_any_address_without_allow_rules() {
+        let rules = "deny 192.168.1.2".parse::<AccessRules>().unwrap();
+
+        assert!(rules.is_address_allowed("10.2.3.4".parse().unwrap()));
+        assert!(!rules.is_address_allowed("192.168.1.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn should_ban_and_unban() {
+        let mut rules = AccessRules::default();
+
+        assert!(rules.ban("alice"));
+        assert!(rules.ban("192.168.1.0/24"));
+        assert!(rules.is_banned("alice"));
+        assert!(!rules.is_address_allowed("192.168.1.2".parse().unwrap()));
+        assert_eq!("deny 192.168.1.0/24\nban alice\n", rules.to_string());
+
+        assert!(rules.unban("alice"));
+        assert!(rules.unban("192.168.1.0/24"));
+        assert!(!rules.is_banned("alice"));
+        assert!(rules.is_address_allowed("192.168.1.2".parse().unwrap()));
+    }
+}
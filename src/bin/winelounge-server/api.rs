@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use log::info;
+use serde::Serialize;
+use subtle::ConstantTimeEq;
+use winelounge::net::BoxAreaContent;
+
+use crate::http::{Request, Response};
+use crate::server::{Room, Server};
+use crate::session::PlayerState;
+
+#[derive(Serialize)]
+struct Status {
+    uptime: i64,
+    rooms: Vec<RoomStatus>,
+}
+
+#[derive(Serialize)]
+struct RoomStatus {
+    name: String,
+    players: Vec<PlayerStatus>,
+}
+
+#[derive(Serialize)]
+struct PlayerStatus {
+    id: String,
+    name: Option<String>,
+    connected: bool,
+    points: u32,
+}
+
+#[derive(Serialize)]
+struct World<'a> {
+    box_areas: &'a BTreeMap<String, BoxAreaContent>,
+    players: Vec<&'a PlayerState>,
+}
+
+/// The admin API, handling requests with matching bearer token only.
+///
+/// * `GET /status` - uptime, rooms, players and their scores
+/// * `GET /rooms/<name>/world` - box areas and players of a room
+/// * `POST /rooms/<name>/kick/<player_id>` - removes a player
+/// * `POST /rooms/<name>/reset` - restores the starting box area contents and resets players
+pub struct Api {
+    server: Arc<Mutex<Server>>,
+    token: Option<String>,
+}
+
+impl Api {
+    /// Creates the admin API. Without a token, all requests are rejected.
+    pub fn new(server: Arc<Mutex<Server>>, token: Option<String>) -> Api {
+        Api { server, token }
+    }
+
+    /// Handles a request to the admin API
+    pub fn handle(&self, request: &Request) -> Response {
+        if !self.is_authorized(request) {
+            return Response::unauthorized();
+        }
+
+        let path = request.path.split('/').skip(1).collect::<Vec<_>>();
+        let mut server = self.server.lock().unwrap();
+
+        match (request.method.as_str(), path.as_slice()) {
+            ("GET", ["status"]) => Response::json(Self::status(&server)),
+            ("GET", ["rooms", room, "world"]) => match server.rooms.get(*room) {
+                Some(room) => Response::json(Self::world(room)),
+                None => Response::not_found(),
+            },
+            ("POST", ["rooms", room, "kick", player_id]) => {
+                if server.kick(room, player_id) {
+                    info!("Kicked player {} from room {}", player_id, room);
+                    Response::json("{}".to_string())
+                } else {
+                    Response::not_found()
+                }
+            }
+            ("POST", ["rooms", room, "reset"]) => {
+                if server.reset(room) {
+                    info!("Reset room {}", room);
+                    Response::json("{}".to_string())
+                } else {
+                    Response::not_found()
+                }
+            }
+            _ => Response::not_found(),
+        }
+    }
+
+    fn is_authorized(&self, request: &Request) -> bool {
+        match (&self.token, request.headers.get("authorization")) {
+            // Compared in constant time, so response times tell nothing about the token
+            (Some(token), Some(authorization)) => match authorization.strip_prefix("Bearer ") {
+                Some(bearer) => bearer.as_bytes().ct_eq(token.as_bytes()).into(),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn status(server: &Server) -> String {
+        let status = Status {
+            uptime: chrono::Utc::now().timestamp() - server.started_at,
+            rooms: server
+                .rooms
+                .iter()
+                .map(|(name, room)| RoomStatus {
+                    name: name.clone(),
+                    players: room
+                        .sessions
+                        .players()
+                        .into_iter()
+                        .map(|player| PlayerStatus {
+                            id: player.id.clone(),
+                            name: player.name.clone(),
+                            connected: room.sessions.is_connected(&player.id),
+                            points: player.points,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+        serde_json::to_string(&status).unwrap_or_default()
+    }
+
+    fn world(room: &Room) -> String {
+        let world = World {
+            box_areas: &room.box_areas,
+            players: room.sessions.players(),
+        };
+        serde_json::to_string(&world).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use winelounge::net::BoxAreaContent;
+
+    use crate::api::Api;
+    use crate::config::Config;
+    use crate::http::Request;
+    use crate::leaderboard::Leaderboard;
+    use crate::server::Server;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    fn api() -> (Api, Arc<Mutex<Server>>) {
+        let server = Server::new(Config::default(), None, Leaderboard::in_memory());
+        let server = Arc::new(Mutex::new(server));
+        (Api::new(server.clone(), Some(TOKEN.to_string())), server)
+    }
+
+    fn request(method: &str, path: &str, token: Option<&str>) -> Request {
+        let headers = token
+            .map(|token| ("authorization".to_string(), format!("Bearer {}", token)))
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers,
+        }
+    }
+
+    #[test]
+    fn should_reject_requests_without_matching_token() {
+        let (api, _) = api();
+        assert_eq!(401, api.handle(&request("GET", "/status", None)).status);
+        assert_eq!(
+            401,
+            api.handle(&request("GET", "/status", Some("fedcba9876543210")))
+                .status
+        );
+        let api = Api::new(api.server.clone(), None);
+        assert_eq!(
+            401,
+            api.handle(&request("GET", "/status", Some(TOKEN))).status
+        );
+    }
+
+    #[test]
+    fn should_report_status_and_world_of_rooms() {
+        let (api, server) = api();
+        let (_, player) = server
+            .lock()
+            .unwrap()
+            .rooms
+            .get_mut("lounge")
+            .unwrap()
+            .sessions
+            .join(None, None, 0);
+
+        let response = api.handle(&request("GET", "/status", Some(TOKEN)));
+        assert_eq!(200, response.status);
+        assert!(response.body.contains(&player.id));
+
+        let response = api.handle(&request("GET", "/rooms/lounge/world", Some(TOKEN)));
+        assert_eq!(200, response.status);
+        assert!(response.body.contains("\"RightTop\""));
+        assert!(response.body.contains(&player.id));
+
+        assert_eq!(
+            404,
+            api.handle(&request("GET", "/rooms/cellar/world", Some(TOKEN)))
+                .status
+        );
+        assert_eq!(
+            404,
+            api.handle(&request("GET", "/rooms", Some(TOKEN))).status
+        );
+    }
+
+    #[test]
+    fn should_kick_players() {
+        let (api, server) = api();
+        let (_, player) = server
+            .lock()
+            .unwrap()
+            .rooms
+            .get_mut("lounge")
+            .unwrap()
+            .sessions
+            .join(None, None, 0);
+        let path = format!("/rooms/lounge/kick/{}", player.id);
+
+        assert_eq!(200, api.handle(&request("POST", &path, Some(TOKEN))).status);
+        assert!(server.lock().unwrap().rooms["lounge"]
+            .sessions
+            .players()
+            .is_empty());
+        assert_eq!(404, api.handle(&request("POST", &path, Some(TOKEN))).status);
+        assert_eq!(
+            404,
+            api.handle(&request(
+                "POST",
+                &format!("/rooms/cellar/kick/{}", player.id),
+                Some(TOKEN)
+            ))
+            .status
+        );
+    }
+
+    #[test]
+    fn should_reset_rooms() {
+        let (api, server) = api();
+        let token = {
+            let mut server = server.lock().unwrap();
+            let room = server.rooms.get_mut("lounge").unwrap();
+            room.box_areas
+                .insert("RightTop".to_string(), BoxAreaContent::Nothing);
+            let (token, _) = room.sessions.join(None, None, 0);
+            room.sessions.player_mut(&token).unwrap().points = 3;
+            token
+        };
+
+        let response = api.handle(&request("POST", "/rooms/lounge/reset", Some(TOKEN)));
+        assert_eq!(200, response.status);
+        let mut server = server.lock().unwrap();
+        let room = server.rooms.get_mut("lounge").unwrap();
+        assert_eq!(BoxAreaContent::EmptyGlass, room.box_areas["RightTop"]);
+        assert_eq!(0, room.sessions.player_mut(&token).unwrap().points);
+        drop(server);
+
+        assert_eq!(
+            404,
+            api.handle(&request("POST", "/rooms/cellar/reset", Some(TOKEN)))
+                .status
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use subtle::ConstantTimeEq;
+
+/// File containing player names with their hashed passwords
+pub const CREDENTIALS_FILE: &str = "winelounge.credentials";
+
+/// Environment variable containing the password required to join the server
+pub const SERVER_PASSWORD_VARIABLE: &str = "WINELOUNGE_SERVER_PASSWORD";
+
+/// `Password` and `Login` lines a client may send during one handshake
+pub const MAX_ATTEMPTS: usize = 3;
+
+/// Failed authentications of an address within `FAILURE_WINDOW`, before further ones are
+/// rejected without checking
+const MAX_FAILURES: usize = 5;
+
+/// Seconds failed authentications of an address are remembered
+const FAILURE_WINDOW: i64 = 600;
+
+/// Player credentials, one line per player: `<name> <argon2 hash>`
+#[derive(Default)]
+pub struct Credentials {
+    players: BTreeMap<String, String>,
+}
+
+impl Credentials {
+    /// Adds or replaces the credentials of a player
+    pub fn add(&mut self, name: &str, password: &str) -> Result<(), String> {
+        if !is_valid_name(name) {
+            return Err(format!("Invalid player name '{}'", name));
+        }
+
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| e.to_string())?;
+        self.players.insert(name.to_string(), hash.to_string());
+        Ok(())
+    }
+
+    /// Checks the password of a player
+    pub fn verify(&self, name: &str, password: &str) -> bool {
+        match self.players.get(name).map(|hash| PasswordHash::new(hash)) {
+            Some(Ok(hash)) => Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok(),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, hash) in &self.players {
+            writeln!(f, "{} {}", name, hash)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Credentials {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut credentials = Credentials::default();
+        for line in s.lines().filter(|line| !line.is_empty()) {
+            match line.split_once(' ') {
+                Some((name, hash)) if is_valid_name(name) => {
+                    credentials
+                        .players
+                        .insert(name.to_string(), hash.to_string());
+                }
+                _ => return Err(()),
+            }
+        }
+        Ok(credentials)
+    }
+}
+
+/// Player names consist of up to 16 alphanumeric characters, '-' or '_'
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 16
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Checks server password and player credentials during handshake
+#[derive(Default)]
+pub struct Authenticator {
+    server_password: Option<String>,
+    credentials: Option<Credentials>,
+    /// Times of recent failed authentications per address
+    failures: Mutex<HashMap<IpAddr, Vec<i64>>>,
+}
+
+impl Authenticator {
+    /// Creates an authenticator. Without credentials, players cannot log in and remain anonymous.
+    pub fn new(server_password: Option<String>, credentials: Option<Credentials>) -> Authenticator {
+        Authenticator {
+            server_password,
+            credentials,
+            failures: Mutex::default(),
+        }
+    }
+
+    /// Authenticates a player joining from given address at given time in seconds and returns
+    /// the authenticated player name.
+    ///
+    /// If credentials are configured, players are required to log in.
+    /// Addresses with too many recent failures are rejected without checking.
+    /// Returns the reason if the player is rejected.
+    pub fn authenticate(
+        &self,
+        address: IpAddr,
+        password: Option<&str>,
+        login: Option<(&str, &str)>,
+        now: i64,
+    ) -> Result<Option<String>, String> {
+        {
+            let mut failures = self.failures.lock().unwrap();
+            failures.retain(|_, times| {
+                times.retain(|time| time + FAILURE_WINDOW > now);
+                !times.is_empty()
+            });
+            if failures.get(&address).map_or(0, Vec::len) >= MAX_FAILURES {
+                return Err("Too many failed attempts".to_string());
+            }
+        }
+
+        let authenticated = self.check(password, login);
+        if authenticated.is_err() {
+            self.failures
+                .lock()
+                .unwrap()
+                .entry(address)
+                .or_default()
+                .push(now);
+        }
+        authenticated
+    }
+
+    fn check(
+        &self,
+        password: Option<&str>,
+        login: Option<(&str, &str)>,
+    ) -> Result<Option<String>, String> {
+        if let Some(server_password) = &self.server_password {
+            let matches = password.is_some_and(|password| {
+                password.as_bytes().ct_eq(server_password.as_bytes()).into()
+            });
+            if !matches {
+                return Err("Wrong server password".to_string());
+            }
+        }
+
+        match (&self.credentials, login) {
+            (Some(credentials), Some((name, password))) => {
+                if credentials.verify(name, password) {
+                    Ok(Some(name.to_string()))
+                } else {
+                    Err("Wrong player name or password".to_string())
+                }
+            }
+            (Some(_), None) => Err("Login required".to_string()),
+            (None, Some(_)) => Err("Login not supported".to_string()),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::auth::{Authenticator, Credentials, FAILURE_WINDOW, MAX_FAILURES};
+
+    const ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn should_verify_hashed_password() {
+        let mut credentials = Credentials::default();
+        credentials.add("alice", "secret").unwrap();
+
+        let credentials = credentials.to_string().parse::<Credentials>().unwrap();
+
+        assert!(credentials.verify("alice", "secret"));
+        assert!(!credentials.verify("alice", "wrong"));
+        assert!(!credentials.verify("bob", "secret"));
+        assert!(!credentials.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn should_authenticate_player() {
+        let mut credentials = Credentials::default();
+        credentials.add("alice", "secret").unwrap();
+        let authenticator = Authenticator::new(Some("lounge".to_string()), Some(credentials));
+
+        assert_eq!(
+            Ok(Some("alice".to_string())),
+            authenticator.authenticate(ADDRESS, Some("lounge"), Some(("alice", "secret")), 0)
+        );
+        assert!(authenticator
+            .authenticate(ADDRESS, None, Some(("alice", "secret")), 0)
+            .is_err());
+        assert!(authenticator
+            .authenticate(ADDRESS, Some("lounge"), Some(("alice", "wrong")), 0)
+            .is_err());
+        assert!(authenticator
+            .authenticate(ADDRESS, Some("lounge"), None, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn should_reject_address_after_too_many_failures() {
+        let authenticator = Authenticator::new(Some("lounge".to_string()), None);
+        for _ in 0..MAX_FAILURES {
+            assert_eq!(
+                Err("Wrong server password".to_string()),
+                authenticator.authenticate(ADDRESS, Some("wrong"), None, 1000)
+            );
+        }
+
+        assert_eq!(
+            Err("Too many failed attempts".to_string()),
+            authenticator.authenticate(ADDRESS, Some("lounge"), None, 1000)
+        );
+        assert_eq!(
+            Ok(None),
+            authenticator.authenticate("10.0.0.1".parse().unwrap(), Some("lounge"), None, 1000)
+        );
+        assert_eq!(
+            Ok(None),
+            authenticator.authenticate(ADDRESS, Some("lounge"), None, 1000 + FAILURE_WINDOW)
+        );
+    }
+
+    #[test]
+    fn should_allow_anonymous_players_without_credentials() {
+        let authenticator = Authenticator::default();
+
+        assert_eq!(Ok(None), authenticator.authenticate(ADDRESS, None, None, 0));
+        assert!(authenticator
+            .authenticate(ADDRESS, None, Some(("alice", "secret")), 0)
+            .is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+use winelounge::geometry::Point;
+use winelounge::level::Level;
+use winelounge::lifecycle::Lifecycle;
+use winelounge::player::Player;
+
+/// File the configuration is read from, if no other file is given using `--config`
+pub const CONFIG_FILE: &str = "winelounge.toml";
+
+/// Server configuration, read from a TOML file.
+///
+/// Missing values default to those of the original game.
+/// Rules and maximum players of existing rooms and new rooms are applied on SIGHUP,
+/// any other change requires a restart.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: String,
+    pub http_address: String,
+    /// Server ticks per second
+    pub tick_rate: u32,
+    /// Bearer token required by the admin API, without a token the admin API is disabled
+    pub admin_token: Option<String>,
+    pub rooms: Vec<RoomConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "0.0.0.0:7888".to_string(),
+            http_address: "0.0.0.0:9888".to_string(),
+            tick_rate: 1,
+            admin_token: None,
+            rooms: vec![RoomConfig::default()],
+        }
+    }
+}
+
+/// A room hosted by the server
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomConfig {
+    pub name: String,
+    pub max_players: usize,
+    /// Seconds a round lasts, scores are recorded at its end
+    pub round_length: u32,
+    pub rules: Rules,
+    /// Transitions box areas go through on their own, overriding those of the level and the rules
+    pub lifecycle: Option<Lifecycle>,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        RoomConfig {
+            name: "lounge".to_string(),
+            max_players: 16,
+            round_length: 600,
+            rules: Rules::default(),
+            lifecycle: None,
+        }
+    }
+}
+
+/// Game rules of a room, sent to players when joining and on change
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    /// Number of glasses a player can carry
+    pub glass_space: u8,
+    pub spawn_x: i32,
+    pub spawn_y: i32,
+    /// Seconds until an empty box area gets a new hidden box
+    pub box_refill_after: u32,
+    /// Seconds until the content of a box area is removed
+    pub box_clear_after: u32,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            glass_space: 5,
+            spawn_x: 380,
+            spawn_y: 250,
+            box_refill_after: 10,
+            box_clear_after: 30,
+        }
+    }
+}
+
+impl Config {
+    /// Reads and validates the configuration file for rooms playing given level
+    pub fn load(file: &str, level: &Level) -> Result<Config, String> {
+        let content =
+            std::fs::read_to_string(file).map_err(|e| format!("Cannot read {}: {}", file, e))?;
+        let config = toml::from_str::<Config>(&content)
+            .map_err(|e| format!("Cannot parse {}: {}", file, e))?;
+        config
+            .validate(level)
+            .map_err(|e| format!("Invalid configuration in {}:\n{}", file, e))?;
+        Ok(config)
+    }
+
+    /// Checks values to be within sensible limits for rooms playing given level, returns all
+    /// errors found, one per line.
+    pub fn validate(&self, level: &Level) -> Result<(), String> {
+        let mut errors = vec![];
+
+        if !(1..=100).contains(&self.tick_rate) {
+            errors.push(format!(
+                "tick_rate: must be between 1 and 100, got {}",
+                self.tick_rate
+            ));
+        }
+        if let Some(token) = self.admin_token.as_ref().filter(|token| token.len() < 16) {
+            errors.push(format!(
+                "admin_token: must be at least 16 characters, got {}",
+                token.len()
+            ));
+        }
+        if self.rooms.is_empty() {
+            errors.push("rooms: at least one room is required".to_string());
+        }
+
+        let mut names = BTreeSet::new();
+        for (index, room) in self.rooms.iter().enumerate() {
+            let prefix = format!("rooms[{}]", index);
+            if room.name.is_empty() || room.name.contains(|c: char| c.is_whitespace() || c == '/') {
+                errors.push(format!(
+                    "{}.name: must not be empty or contain spaces or slashes, got '{}'",
+                    prefix, room.name
+                ));
+            } else if !names.insert(room.name.as_str()) {
+                errors.push(format!(
+                    "{}.name: duplicate room name '{}'",
+                    prefix, room.name
+                ));
+            }
+            if room.max_players == 0 {
+                errors.push(format!("{}.max_players: must be at least 1", prefix));
+            }
+            if room.round_length < 10 {
+                errors.push(format!(
+                    "{}.round_length: must be at least 10, got {}",
+                    prefix, room.round_length
+                ));
+            }
+            errors.extend(
+                room.rules
+                    .validate(level)
+                    .into_iter()
+                    .map(|error| format!("{}.rules.{}", prefix, error)),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// Returns the configuration of a room
+    pub fn room(&self, name: &str) -> Option<&RoomConfig> {
+        self.rooms.iter().find(|room| room.name == name)
+    }
+}
+
+impl Rules {
+    fn validate(&self, level: &Level) -> Vec<String> {
+        let mut errors = vec![];
+
+        if !(1..=10).contains(&self.glass_space) {
+            errors.push(format!(
+                "glass_space: must be between 1 and 10, got {}",
+                self.glass_space
+            ));
+        }
+        let spawn = Player::spawn("", 0, 0).at(Point::new(self.spawn_x, self.spawn_y));
+        if !level.can_stand(&spawn) {
+            errors.push(format!(
+                "spawn_x, spawn_y: player must fit into the playable area off any stone, got ({}, {})",
+                self.spawn_x, self.spawn_y
+            ));
+        }
+        if self.box_clear_after == 0 {
+            errors.push("box_clear_after: must be at least 1".to_string());
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use winelounge::geometry::Point;
+    use winelounge::level::Level;
+    use winelounge::net::BoxAreaContent;
+
+    use crate::config::{Config, RoomConfig, Rules};
+
+    #[test]
+    fn should_use_defaults_for_missing_values() {
+        let config = toml::from_str::<Config>(
+            "tick_rate = 10\n\n[[rooms]]\nname = \"cellar\"\n\n[rooms.rules]\nglass_space = 3\n",
+        )
+        .unwrap();
+
+        assert_eq!("0.0.0.0:7888", config.address);
+        assert_eq!(10, config.tick_rate);
+        assert_eq!("cellar", config.rooms[0].name);
+        assert_eq!(16, config.rooms[0].max_players);
+        assert_eq!(3, config.rooms[0].rules.glass_space);
+        assert_eq!(Rules::default().spawn_x, config.rooms[0].rules.spawn_x);
+        assert!(config.validate(&Level::default()).is_ok());
+    }
+
+    #[test]
+    fn should_read_lifecycle_of_room() {
+        let config = toml::from_str::<Config>(
+            "[[rooms]]\nname = \"cellar\"\n\n[[rooms.lifecycle]]\nfrom = \"Nothing\"\nto = \"EmptyGlass\"\nafter = 5\n",
+        )
+        .unwrap();
+
+        let lifecycle = config.rooms[0].lifecycle.as_ref().unwrap();
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            lifecycle
+                .transition(&BoxAreaContent::Nothing)
+                .map(|transition| transition.after)
+        );
+        assert_eq!(None, RoomConfig::default().lifecycle);
+    }
+
+    #[test]
+    fn should_reject_unknown_fields() {
+        assert!(toml::from_str::<Config>("glass_space = 3\n").is_err());
+    }
+
+    #[test]
+    fn should_require_long_admin_token() {
+        let config = toml::from_str::<Config>("admin_token = \"secret\"\n").unwrap();
+
+        assert_eq!(
+            Err("admin_token: must be at least 16 characters, got 6".to_string()),
+            config.validate(&Level::default())
+        );
+    }
+
+    #[test]
+    fn should_check_spawn_against_level() {
+        let config = toml::from_str::<Config>(
+            "[[rooms]]\nname = \"lounge\"\n\n[rooms.rules]\nspawn_x = 900\nspawn_y = 600\n",
+        )
+        .unwrap();
+        let mut level = Level {
+            width: 1200,
+            height: 900,
+            ..Level::default()
+        };
+
+        assert!(config.validate(&level).is_ok());
+        assert!(config.validate(&Level::default()).is_err());
+
+        level.stones.push(Point::new(900, 650));
+        assert!(config.validate(&level).is_err());
+    }
+
+    #[test]
+    fn should_report_all_invalid_values() {
+        let config = toml::from_str::<Config>(
+            "tick_rate = 0\n\n[[rooms]]\nname = \"lounge\"\n\n[[rooms]]\nname = \"lounge\"\nmax_players = 0\n\n[rooms.rules]\nspawn_y = 600\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Err("tick_rate: must be between 1 and 100, got 0\n\
+                rooms[1].name: duplicate room name 'lounge'\n\
+                rooms[1].max_players: must be at least 1\n\
+                rooms[1].rules.spawn_x, spawn_y: player must fit into the playable area off any stone, got (380, 600)"
+                .to_string()),
+            config.validate(&Level::default())
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, warn};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Time a client has to send its request line and headers
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum length of the request line and of each header line in bytes
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+/// Maximum number of headers of a request
+const MAX_HEADERS: usize = 100;
+
+/// A minimal HTTP request. Request bodies are not supported.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+}
+
+/// A HTTP response with its complete body
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: String) -> Response {
+        Response {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn json(body: String) -> Response {
+        Response::ok("application/json", body)
+    }
+
+    pub fn unauthorized() -> Response {
+        Response {
+            status: 401,
+            content_type: "text/plain",
+            body: "Unauthorized".to_string(),
+        }
+    }
+
+    pub fn method_not_allowed() -> Response {
+        Response {
+            status: 405,
+            content_type: "text/plain",
+            body: "Method Not Allowed".to_string(),
+        }
+    }
+
+    pub fn bad_request() -> Response {
+        Response {
+            status: 400,
+            content_type: "text/plain",
+            body: "Bad Request".to_string(),
+        }
+    }
+
+    pub fn header_fields_too_large() -> Response {
+        Response {
+            status: 431,
+            content_type: "text/plain",
+            body: "Request Header Fields Too Large".to_string(),
+        }
+    }
+
+    pub fn not_found() -> Response {
+        Response {
+            status: 404,
+            content_type: "text/plain",
+            body: "Not Found".to_string(),
+        }
+    }
+
+    fn reason(&self) -> &str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            431 => "Request Header Fields Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// Serves HTTP requests on given listener using the handler to create responses.
+pub async fn serve<F>(listener: TcpListener, handler: F)
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    loop {
+        match listener.accept().await {
+            Ok((socket, address)) => {
+                debug!("HTTP connection from {}", address);
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(socket, handler.as_ref()).await {
+                        warn!("Cannot handle HTTP request: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Cannot accept HTTP connection: {}", e),
+        }
+    }
+}
+
+async fn handle_connection<F>(socket: TcpStream, handler: &F) -> std::io::Result<()>
+where
+    F: Fn(Request) -> Response,
+{
+    let (reader, mut writer) = socket.into_split();
+    let mut reader = BufReader::new(reader);
+    let response = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut reader)).await {
+        Ok(Ok(request)) => handler(request),
+        Ok(Err(response)) => response,
+        Err(_) => return Err(ErrorKind::TimedOut.into()),
+    };
+
+    writer
+        .write_all(
+            format!(
+                "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.status,
+                response.reason(),
+                response.content_type,
+                response.body.len(),
+                response.body
+            )
+            .as_bytes(),
+        )
+        .await?;
+    writer.shutdown().await
+}
+
+/// Reads request line and headers, or returns the response rejecting a malformed request
+async fn read_request(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<Request, Response> {
+    let request_line = read_line(reader).await.ok_or_else(Response::bad_request)?;
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let line = read_line(reader)
+            .await
+            .ok_or_else(Response::header_fields_too_large)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(Response::header_fields_too_large());
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    Ok(Request {
+        method,
+        path,
+        headers,
+    })
+}
+
+/// Reads a line without its line ending, or nothing if it is too long or cannot be read
+async fn read_line(reader: &mut (impl AsyncBufRead + Unpin)) -> Option<String> {
+    let mut line = vec![];
+    reader
+        .take(MAX_LINE_LENGTH + 1)
+        .read_until(b'\n', &mut line)
+        .await
+        .ok()?;
+    if line.pop() != Some(b'\n') {
+        return None;
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::http::{serve, Response};
+
+    async fn respond_to(request: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, |request| {
+            Response::ok("text/plain", request.path)
+        }));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn should_route_paths_without_query() {
+        let response = respond_to(b"GET /metrics?x=1 HTTP/1.1\r\nHost: a\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n/metrics"));
+    }
+
+    #[tokio::test]
+    async fn should_reject_too_long_header_lines() {
+        let mut request = b"GET / HTTP/1.1\r\nCookie: ".to_vec();
+        request.extend(vec![b'a'; 10_000]);
+        request.extend(b"\r\n\r\n");
+        let response = respond_to(&request).await;
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    }
+
+    #[tokio::test]
+    async fn should_reject_too_many_headers() {
+        let mut request = b"GET / HTTP/1.1\r\n".to_vec();
+        for index in 0..200 {
+            request.extend(format!("X-Header-{}: a\r\n", index).as_bytes());
+        }
+        request.extend(b"\r\n");
+        let response = respond_to(&request).await;
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::{Arc, Mutex};
+
+use log::error;
+use rusqlite::{params, Connection, OptionalExtension};
+use tokio::task::JoinHandle;
+
+/// Database file containing the leaderboard
+pub const LEADERBOARD_FILE: &str = "winelounge.db";
+
+/// Best score of each player name, stored in an SQLite database.
+///
+/// Only players with a name are recorded, as anonymous players cannot be recognized in later rounds.
+/// The database is accessed on the blocking thread pool, so game connections never wait for it.
+#[derive(Clone)]
+pub struct Leaderboard {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Leaderboard {
+    /// Opens the leaderboard database, creating it if required
+    pub fn open(file: &str) -> Result<Leaderboard, String> {
+        Self::init(Connection::open(file).map_err(|e| format!("Cannot open {}: {}", file, e))?)
+    }
+
+    /// Creates a leaderboard not persisted to disk
+    #[cfg(test)]
+    pub fn in_memory() -> Leaderboard {
+        Self::init(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn init(connection: Connection) -> Result<Leaderboard, String> {
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS scores (name TEXT PRIMARY KEY, points INTEGER NOT NULL, recorded_at INTEGER NOT NULL)",
+                [],
+            )
+            .map_err(|e| format!("Cannot create leaderboard: {}", e))?;
+        Ok(Leaderboard {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Records the final scores of players in the background, keeping the best score of each player
+    pub fn record(&self, scores: Vec<(String, u32)>, now: i64) -> JoinHandle<()> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.lock().unwrap();
+            for (name, points) in scores {
+                if let Err(e) = record(&connection, &name, points, now) {
+                    error!("{}", e);
+                }
+            }
+        })
+    }
+
+    /// Returns the names and points of the best players, best first
+    pub async fn top(&self, count: usize) -> Result<Vec<(String, u32)>, String> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || top(&connection.lock().unwrap(), count))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    /// Returns the rank, starting with 1, and the points of a player
+    pub async fn rank(&self, name: &str) -> Result<Option<(usize, u32)>, String> {
+        let connection = self.connection.clone();
+        let name = name.to_string();
+        tokio::task::spawn_blocking(move || rank(&connection.lock().unwrap(), &name))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+}
+
+/// Records the final score of a player, if it is the best score of this player so far
+fn record(connection: &Connection, name: &str, points: u32, now: i64) -> Result<(), String> {
+    connection
+            .execute(
+                "INSERT INTO scores (name, points, recorded_at) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT (name) DO UPDATE SET points = excluded.points, recorded_at = excluded.recorded_at \
+                 WHERE excluded.points > scores.points",
+                params![name, points, now],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Cannot record score of {}: {}", name, e))
+}
+
+fn top(connection: &Connection, count: usize) -> Result<Vec<(String, u32)>, String> {
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT name, points FROM scores ORDER BY points DESC, recorded_at ASC LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map(params![count as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn rank(connection: &Connection, name: &str) -> Result<Option<(usize, u32)>, String> {
+    connection
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM scores AS better \
+                 WHERE better.points > scores.points \
+                 OR (better.points = scores.points AND better.recorded_at < scores.recorded_at)) + 1, points \
+                 FROM scores WHERE name = ?1",
+                params![name],
+                |row| Ok((row.get::<_, i64>(0)? as usize, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::leaderboard::Leaderboard;
+
+    #[tokio::test]
+    async fn should_keep_best_score_per_player() {
+        let leaderboard = Leaderboard::in_memory();
+        let score = |name: &str, points| vec![(name.to_string(), points)];
+        leaderboard.record(score("alice", 30), 1000).await.unwrap();
+        leaderboard.record(score("bob", 50), 1001).await.unwrap();
+        leaderboard
+            .record(
+                vec![("alice".to_string(), 20), ("carol".to_string(), 50)],
+                1002,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![("bob".to_string(), 50), ("carol".to_string(), 50)],
+            leaderboard.top(2).await.unwrap()
+        );
+        assert_eq!(Some((3, 30)), leaderboard.rank("alice").await.unwrap());
+        assert_eq!(Some((2, 50)), leaderboard.rank("carol").await.unwrap());
+        assert_eq!(None, leaderboard.rank("dave").await.unwrap());
+    }
+}
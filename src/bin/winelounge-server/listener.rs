@@ -0,0 +1,32 @@
+use std::future::Future;
+use std::net::SocketAddr;
+
+use tokio::net::{TcpListener, TcpStream};
+use winelounge::transport::Stream;
+
+/// Source of incoming player connections
+pub trait Listener: Send + 'static {
+    type Stream: Stream;
+
+    /// Waits for the next connection and returns it with the address of the peer
+    fn accept(
+        &mut self,
+    ) -> impl Future<Output = std::io::Result<(Self::Stream, SocketAddr)>> + Send;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&mut self) -> std::io::Result<(TcpStream, SocketAddr)> {
+        TcpListener::accept(self).await
+    }
+}
+
+#[cfg(test)]
+impl Listener for winelounge::transport::loopback::LoopbackListener {
+    type Stream = tokio::io::DuplexStream;
+
+    async fn accept(&mut self) -> std::io::Result<(tokio::io::DuplexStream, SocketAddr)> {
+        winelounge::transport::loopback::LoopbackListener::accept(self).await
+    }
+}
@@ -1,12 +1,966 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, Notify};
+use tokio_rustls::TlsAcceptor;
+use winelounge::geometry::Point;
+use winelounge::level::Level;
+use winelounge::net::BoxAreaContent;
+use winelounge::protocol::Message;
+use winelounge::recording::Recorder;
+use winelounge::transport::{simulate, NetworkConditions, Stream};
+
+use crate::access::{AccessRules, ACCESS_FILE};
+use crate::api::Api;
+use crate::auth::{
+    Authenticator, Credentials, CREDENTIALS_FILE, MAX_ATTEMPTS, SERVER_PASSWORD_VARIABLE,
+};
+use crate::config::{Config, CONFIG_FILE};
+use crate::http::Response;
+use crate::leaderboard::{Leaderboard, LEADERBOARD_FILE};
+use crate::listener::Listener;
+use crate::server::Server;
+use crate::session::PlayerState;
+use crate::snapshot::{Snapshot, SNAPSHOT_FILE};
+
+mod access;
+mod api;
+mod auth;
+mod config;
+mod http;
+mod leaderboard;
+mod listener;
+mod metrics;
+mod movement;
+mod server;
+mod session;
+mod snapshot;
+mod svg;
+mod tls;
+
 #[tokio::main]
 async fn main() {
+    simple_logger::SimpleLogger::new().env().init().unwrap();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(index) = args.iter().position(|arg| arg == "--add-player") {
+        add_player(args.get(index + 1).expect("Missing player name"));
+        return;
+    }
+
+    let level = match arg_value(&args, "--level").map(|file| Level::load(file)) {
+        Some(Ok(level)) => {
+            info!("Playing level {}", arg_value(&args, "--level").unwrap());
+            Some(level)
+        }
+        Some(Err(e)) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let config_file = arg_value(&args, "--config").map_or(CONFIG_FILE, String::as_str);
+    let config = if config_file == CONFIG_FILE && !Path::new(CONFIG_FILE).exists() {
+        info!("No {} found, using default configuration", CONFIG_FILE);
+        Config::default()
+    } else {
+        match Config::load(config_file, level.as_ref().unwrap_or(&Level::default())) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let credentials = match std::fs::read_to_string(CREDENTIALS_FILE) {
+        Ok(content) => Some(
+            content
+                .parse::<Credentials>()
+                .expect("Cannot parse credentials file"),
+        ),
+        Err(_) => None,
+    };
+    let authenticator = Arc::new(Authenticator::new(
+        std::env::var(SERVER_PASSWORD_VARIABLE).ok(),
+        credentials,
+    ));
+
+    let acceptor = match (
+        arg_value(&args, "--tls-cert"),
+        arg_value(&args, "--tls-key"),
+    ) {
+        (Some(cert_file), Some(key_file)) => {
+            let (acceptor, fingerprint) =
+                tls::acceptor(cert_file, key_file).expect("Cannot configure TLS");
+            info!("TLS enabled, certificate fingerprint {}", fingerprint);
+            Some(acceptor)
+        }
+        (None, None) => None,
+        _ => panic!("Both --tls-cert and --tls-key are required for TLS"),
+    };
+
+    let listener = TcpListener::bind(&config.address)
+        .await
+        .expect("Cannot open socket");
+    let http_address = config.http_address.clone();
+    let admin_token = config.admin_token.clone();
+    let tick_rate = config.tick_rate;
+    let leaderboard = Leaderboard::open(LEADERBOARD_FILE).expect("Cannot open leaderboard");
+    let server = Arc::new(Mutex::new(Server::new(config, level, leaderboard)));
+
+    if let Ok(content) = std::fs::read_to_string(ACCESS_FILE) {
+        server.lock().unwrap().access = content
+            .parse::<AccessRules>()
+            .expect("Cannot parse access file");
+    }
+
+    if args.iter().any(|arg| arg == "--restore") {
+        let snapshot = std::fs::read_to_string(SNAPSHOT_FILE)
+            .expect("Cannot read snapshot file")
+            .parse::<Snapshot>()
+            .expect("Cannot parse snapshot file");
+        snapshot.restore(&mut server.lock().unwrap(), chrono::Utc::now().timestamp());
+        info!("Restored snapshot from {}", SNAPSHOT_FILE);
+    }
+
+    tokio::spawn(tick(server.clone(), tick_rate));
+    tokio::spawn(serve_http(server.clone(), http_address, admin_token));
+    tokio::spawn(console(server.clone()));
+    tokio::spawn(reload_config(server.clone(), config_file.to_string()));
+
+    let conditions = arg_value(&args, "--simulate-network").map(|conditions| {
+        conditions
+            .parse::<NetworkConditions>()
+            .expect("Invalid network conditions")
+    });
+    if let Some(conditions) = &conditions {
+        warn!("Simulating network conditions {:?}", conditions);
+    }
+    let recorder = arg_value(&args, "--record").map(|file| {
+        info!("Recording traffic to {}", file);
+        Recorder::create(file).expect("Cannot create recording")
+    });
+    let layers = Layers {
+        tls: acceptor,
+        conditions,
+        recorder,
+    };
+
+    serve(
+        server.clone(),
+        authenticator,
+        layers,
+        listener,
+        shutdown_signal(),
+    )
+    .await;
+
+    info!("Shutting down");
+    let writers = server.lock().unwrap().shutdown();
+    for writer in writers {
+        let _r = tokio::time::timeout(Duration::from_secs(5), writer).await;
+    }
+
+    let snapshot = Snapshot::of(&server.lock().unwrap());
+    match std::fs::write(SNAPSHOT_FILE, snapshot.to_string()) {
+        Ok(_) => info!("Wrote snapshot to {}", SNAPSHOT_FILE),
+        Err(e) => error!("Cannot write snapshot to {}: {}", SNAPSHOT_FILE, e),
+    }
+}
+
+/// Returns the value following a command line argument
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+}
+
+/// Adds or replaces credentials of a player, reading the password from stdin
+fn add_player(name: &str) {
+    let mut credentials = match std::fs::read_to_string(CREDENTIALS_FILE) {
+        Ok(content) => content
+            .parse::<Credentials>()
+            .expect("Cannot parse credentials file"),
+        Err(_) => Credentials::default(),
+    };
+
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .expect("Cannot read password");
+
+    credentials
+        .add(name, password.trim_end_matches(['\r', '\n']))
+        .expect("Cannot add player");
+    std::fs::write(CREDENTIALS_FILE, credentials.to_string())
+        .expect("Cannot write credentials file");
+    info!("Added player {} to {}", name, CREDENTIALS_FILE);
+}
+
+/// Reads admin commands from stdin: `ban <name|address range>` and `unban <name|address range>`.
+///
+/// Bans are persisted to the access file and banned players are kicked, by name or by address.
+async fn console(server: Arc<Mutex<Server>>) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let access = {
+            let mut server = server.lock().unwrap();
+            match line.trim().split_once(' ') {
+                Some(("ban", target)) => {
+                    if server.access.ban(target) {
+                        info!("Banned {}", target);
+                        server.kick_banned();
+                    }
+                }
+                Some(("unban", target)) => {
+                    if server.access.unban(target) {
+                        info!("Unbanned {}", target);
+                    }
+                }
+                _ => {
+                    warn!("Unknown command '{}'", line);
+                    continue;
+                }
+            }
+            server.access.to_string()
+        };
+        if let Err(e) = tokio::fs::write(ACCESS_FILE, access).await {
+            error!("Cannot write access file {}: {}", ACCESS_FILE, e);
+        }
+    }
+}
+
+/// Reloads the configuration file on SIGHUP.
+///
+/// An invalid configuration is logged and the current configuration is kept.
+async fn reload_config(server: Arc<Mutex<Server>>, config_file: String) {
+    let mut hangup = signal(SignalKind::hangup()).expect("Cannot install signal handler");
+    while hangup.recv().await.is_some() {
+        let level = server.lock().unwrap().level.clone().unwrap_or_default();
+        match Config::load(&config_file, &level) {
+            Ok(config) => {
+                server.lock().unwrap().reload(config);
+                info!("Reloaded configuration from {}", config_file);
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// Waits for SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Cannot install signal handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+/// Serves metrics in Prometheus text format on `/metrics`, a live view of each room
+/// on `/rooms/<name>/view` and the admin API
+async fn serve_http(server: Arc<Mutex<Server>>, address: String, admin_token: Option<String>) {
+    let listener = TcpListener::bind(address)
+        .await
+        .expect("Cannot open HTTP socket");
+    let api = Api::new(server.clone(), admin_token);
+
+    http::serve(listener, move |request| {
+        let path = request.path.split('/').skip(1).collect::<Vec<_>>();
+        match (request.method.as_str(), path.as_slice()) {
+            ("GET", ["metrics"]) => Response::ok(
+                "text/plain; version=0.0.4",
+                server.lock().unwrap().render_metrics(),
+            ),
+            ("GET", ["sprite.svg"]) => Response::ok("image/svg+xml", svg::SPRITE_SHEET.to_string()),
+            ("GET", ["rooms", room, "view"]) => match server.lock().unwrap().rooms.get(*room) {
+                Some(room) => Response::ok("text/html", svg::render_page(room)),
+                None => Response::not_found(),
+            },
+            ("GET", ["rooms", room, "world.svg"]) => {
+                match server.lock().unwrap().rooms.get(*room) {
+                    Some(room) => Response::ok("image/svg+xml", svg::render(room)),
+                    None => Response::not_found(),
+                }
+            }
+            (_, ["metrics"]) => Response::method_not_allowed(),
+            _ => api.handle(&request),
+        }
+    })
+    .await
+}
+
+/// Runs the server tick `tick_rate` times per second: walks moving players, changes box areas
+/// whose timer expired, removes players of disconnected sessions once their grace period has
+/// elapsed and ends rounds that are over
+async fn tick(server: Arc<Mutex<Server>>, tick_rate: u32) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1) / tick_rate);
+    loop {
+        interval.tick().await;
+
+        let start = Instant::now();
+        let mut server = server.lock().unwrap();
+        for room in server.rooms.values_mut() {
+            room.walk(Instant::now());
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        let changes = server
+            .rooms
+            .iter_mut()
+            .flat_map(|(name, room)| {
+                room.expire_box_areas(now)
+                    .into_iter()
+                    .map(|(id, content)| (name.clone(), id, content))
+            })
+            .collect::<Vec<_>>();
+        for (room, id, content) in changes {
+            if content != BoxAreaContent::Nothing && content != BoxAreaContent::EmptyBottle {
+                server.metrics.box_area_spawn(&id, &content);
+            }
+            server.broadcast(&room, "", &Message::UpdateBoxArea(id, content));
+        }
+        let now = chrono::Utc::now().timestamp();
+        let expired = server
+            .rooms
+            .iter_mut()
+            .flat_map(|(name, room)| {
+                room.sessions
+                    .expire(now)
+                    .into_iter()
+                    .map(|player| (name.clone(), player))
+            })
+            .collect::<Vec<_>>();
+        for (room, player) in expired {
+            info!("Session of player {} in room {} expired", player.id, room);
+            server.broadcast(&room, &player.id, &Message::Remove(player.id.clone()));
+        }
+        let over = server
+            .rooms
+            .iter()
+            .filter(|(_, room)| room.is_round_over(now))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for room in over {
+            server.end_round(&room);
+        }
+        server.metrics.tick(start.elapsed());
+    }
+}
+
+/// Layers applied to each accepted connection, from the socket up to the protocol
+#[derive(Clone, Default)]
+struct Layers {
+    tls: Option<TlsAcceptor>,
+    conditions: Option<NetworkConditions>,
+    recorder: Option<Recorder>,
+}
+
+/// Pause before accepting again after accepting a connection failed
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
 
-    let listener = tokio::net::TcpListener::bind(":7888").await.expect("Cannot open socket");
+/// Accepts player connections until shutdown.
+///
+/// Failing to accept a connection, e.g. when running out of file descriptors, is logged
+/// and accepting continues after a short pause.
+async fn serve<L: Listener>(
+    server: Arc<Mutex<Server>>,
+    authenticator: Arc<Authenticator>,
+    layers: Layers,
+    mut listener: L,
+    shutdown: impl Future<Output = ()>,
+) {
+    tokio::pin!(shutdown);
 
-    'listener: loop {
+    let mut connections = 0;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (socket, address) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Cannot accept connection: {}", e);
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                connections += 1;
+                debug!("Connection {} from {}", connections, address);
+                tokio::spawn(accept(
+                    server.clone(),
+                    authenticator.clone(),
+                    layers.clone(),
+                    socket,
+                    address,
+                    connections,
+                ));
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+}
+
+/// Establishes TLS if enabled, simulates network conditions and records traffic if given
+/// and rejects clients with addresses not allowed
+async fn accept<S: Stream>(
+    server: Arc<Mutex<Server>>,
+    authenticator: Arc<Authenticator>,
+    layers: Layers,
+    socket: S,
+    address: SocketAddr,
+    connection: u64,
+) {
+    let allowed = server
+        .lock()
+        .unwrap()
+        .access
+        .is_address_allowed(address.ip());
+    if !allowed {
+        warn!("Rejected connection from {}: Address not allowed", address);
+    }
+
+    let stream: Box<dyn Stream> = match layers.tls {
+        Some(acceptor) => match acceptor.accept(socket).await {
+            Ok(stream) => Box::new(stream),
+            Err(e) => {
+                warn!("TLS handshake with {} failed: {}", address, e);
+                return;
+            }
+        },
+        None => Box::new(socket),
+    };
+    let stream: Box<dyn Stream> = match layers.conditions {
+        Some(conditions) => Box::new(simulate(stream, conditions)),
+        None => stream,
+    };
+    let mut stream = match layers.recorder {
+        Some(recorder) => Box::new(recorder.wrap(stream, connection)),
+        None => stream,
+    };
+
+    if allowed {
+        handle_connection(server, authenticator, stream, address).await
+    } else {
+        reject(&mut stream, "Address not allowed").await
+    }
+}
+
+/// Handles a client connection.
+///
+/// During handshake, the client may send the server `Password`, a player `Login`
+/// and the `Room` to join, defaulting to the first configured room.
+/// The handshake ends with a `Join`, optionally with the session token
+/// of a previous connection to restore the player.
+/// Clients sending too many passwords or logins are disconnected.
+async fn handle_connection<S: Stream>(
+    server: Arc<Mutex<Server>>,
+    authenticator: Arc<Authenticator>,
+    stream: S,
+    address: SocketAddr,
+) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+
+    let mut password = None;
+    let mut login = None;
+    let mut attempts = 0;
+    let mut room = None;
+    let token = loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            _ => return,
+        };
+        let message = match line.parse::<Message>() {
+            Ok(
+                message @ (Message::Password(_)
+                | Message::Login(_, _)
+                | Message::Room(_)
+                | Message::Join(_)),
+            ) => message,
+            _ => {
+                warn!("Expected handshake but got '{}'", line);
+                server.lock().unwrap().metrics.parse_error();
+                reject(&mut writer, "Expected Join").await;
+                return;
+            }
+        };
+        server
+            .lock()
+            .unwrap()
+            .metrics
+            .command_received(message.kind());
+
+        if matches!(message, Message::Password(_) | Message::Login(_, _)) {
+            attempts += 1;
+            if attempts > MAX_ATTEMPTS {
+                warn!("Rejected connection from {}: Too many attempts", address);
+                reject(&mut writer, "Too many attempts").await;
+                return;
+            }
+        }
+        match message {
+            Message::Password(value) => password = Some(value),
+            Message::Login(name, value) => login = Some((name, value)),
+            Message::Room(name) => room = Some(name),
+            Message::Join(token) => break token,
+            _ => {}
+        }
+    };
+
+    let name = match authenticator.authenticate(
+        address.ip(),
+        password.as_deref(),
+        login
+            .as_ref()
+            .map(|(name, password)| (name.as_str(), password.as_str())),
+        chrono::Utc::now().timestamp(),
+    ) {
+        Ok(name) => name,
+        Err(reason) => {
+            warn!("Rejected player from {}: {}", address, reason);
+            reject(&mut writer, &reason).await;
+            return;
+        }
+    };
+
+    let joined = {
+        let mut server = server.lock().unwrap();
+        let server = &mut *server;
+        let room_name = room.unwrap_or_else(|| server.default_room().to_string());
+        match (&name, server.rooms.get_mut(&room_name)) {
+            (Some(name), _) if server.access.is_banned(name) => Err("Player banned"),
+            (_, None) => Err("Unknown room"),
+            (Some(name), Some(room)) if room.sessions.is_name_connected(name) => {
+                Err("Player already connected")
+            }
+            (_, Some(room)) if room.is_full() => Err("Room full"),
+            (_, Some(room)) => {
+                let (token, player) = room.sessions.join(
+                    token.as_deref(),
+                    name.as_deref(),
+                    chrono::Utc::now().timestamp(),
+                );
+                Ok((room_name, token, player))
+            }
+        }
+    };
+    let (room, token, player) = match joined {
+        Ok(joined) => joined,
+        Err(reason) => {
+            warn!("Rejected player {}: {}", name.unwrap_or_default(), reason);
+            reject(&mut writer, reason).await;
+            return;
+        }
+    };
+    info!("Player {} joined room {}", player.id, room);
+
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    let kicked = Arc::new(Notify::new());
+    let writer = tokio::spawn(async move {
+        while let Some(line) = receiver.recv().await {
+            if writer
+                .write_all(format!("{}\n", line).as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+        let _r = writer.shutdown().await;
+    });
+
+    let player_id = {
+        let mut server = server.lock().unwrap();
+        server.connect(
+            &room,
+            &player.id,
+            sender,
+            writer,
+            kicked.clone(),
+            address.ip(),
+        );
+        if let Some(room) = server.rooms.get_mut(&room) {
+            room.enter(&player, Instant::now());
+        }
+        let welcome = Message::Welcome(
+            player.id.clone(),
+            token.clone(),
+            server.rooms[&room].level().hash(),
+        );
+        server.send(&room, &player.id, &welcome);
+        let rules = server.rooms[&room].rules();
+        server.send(&room, &player.id, &rules);
+        let box_areas = server.rooms[&room]
+            .box_areas
+            .iter()
+            .map(|(id, content)| Message::UpdateBoxArea(id.clone(), content.clone()))
+            .collect::<Vec<_>>();
+        for box_area in box_areas {
+            server.send(&room, &player.id, &box_area);
+        }
+        let others = server.rooms[&room]
+            .sessions
+            .connected_players()
+            .into_iter()
+            .filter(|other| other.id != player.id)
+            .flat_map(|other| {
+                let spawn = Message::Spawn(other.id.clone(), other.x, other.y);
+                match &other.name {
+                    Some(name) => vec![spawn, Message::Name(other.id.clone(), name.clone())],
+                    None => vec![spawn],
+                }
+            })
+            .collect::<Vec<_>>();
+        for other in others {
+            server.send(&room, &player.id, &other);
+        }
+        let spawn = Message::Spawn(player.id.clone(), player.x, player.y);
+        server.send(&room, &player.id, &spawn);
+        server.send(
+            &room,
+            &player.id,
+            &Message::Inventory(
+                player.id.clone(),
+                player.empty_glasses,
+                player.filled_glasses,
+                player.points,
+            ),
+        );
+        server.broadcast(&room, &player.id, &spawn);
+        if let Some(name) = &player.name {
+            server.broadcast(
+                &room,
+                &player.id,
+                &Message::Name(player.id.clone(), name.clone()),
+            );
+        }
+
+        player.id
+    };
+
+    let shared = server.clone();
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line,
+            _ = kicked.notified() => break,
+        };
+        let Ok(Some(line)) = line else {
+            break;
+        };
+        let mut server = server.lock().unwrap();
+        let server = &mut *server;
+        let message = match line.parse::<Message>() {
+            Ok(message) => message,
+            Err(_) => {
+                warn!("Cannot parse message '{}' of player {}", line, player_id);
+                server.metrics.parse_error();
+                continue;
+            }
+        };
+        server.metrics.command_received(message.kind());
+
+        let room_state = match server.rooms.get_mut(&room) {
+            Some(room_state) => room_state,
+            None => break,
+        };
+        let player = match room_state.sessions.player_mut(&token) {
+            Some(player) => player.clone(),
+            None => break,
+        };
+
+        let message = match message {
+            Message::Face(_, direction) => Message::Face(player_id.clone(), direction),
+            Message::Move(_, direction) => {
+                room_state.start_moving(&player_id, direction.clone(), Instant::now());
+                Message::Move(player_id.clone(), direction)
+            }
+            Message::Stop(_, x, y) => {
+                let position = match room_state.stop(&player_id, Point::new(x, y), Instant::now()) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                let stop = Message::Stop(player_id.clone(), position.x, position.y);
+                if position != Point::new(x, y) {
+                    // Tell the player where it actually stopped, others get the same position
+                    warn!(
+                        "Correcting implausible stop of player {} at ({}, {})",
+                        player_id, x, y
+                    );
+                    server.send(&room, &player_id, &stop);
+                }
+                stop
+            }
+            // Glasses are picked and filled by the server, clients only tell about glasses drunk
+            Message::Inventory(_, empty_glasses, filled_glasses, points) => {
+                if filled_glasses < player.filled_glasses {
+                    let glasses = player.filled_glasses - filled_glasses;
+                    room_state.drink(&player_id, glasses, Instant::now());
+                }
+                let changed = match room_state.sessions.player_mut(&token) {
+                    Some(changed) => changed.clone(),
+                    None => break,
+                };
+                let inventory = inventory(&changed);
+                if (empty_glasses, filled_glasses, points)
+                    != (
+                        changed.empty_glasses,
+                        changed.filled_glasses,
+                        changed.points,
+                    )
+                {
+                    warn!(
+                        "Correcting inventory {} {} {} of player {}",
+                        empty_glasses, filled_glasses, points, player_id
+                    );
+                    server.send(&room, &player_id, &inventory);
+                }
+                if changed == player {
+                    continue;
+                }
+                inventory
+            }
+            Message::UpdateBoxArea(id, _) if !room_state.has_box_area(&id) => {
+                warn!("Ignoring unknown box area {} of player {}", id, player_id);
+                continue;
+            }
+            // Players tell which box area they reached, the server decides what they take from it
+            Message::UpdateBoxArea(id, content) => {
+                let previous = room_state.box_areas.get(&id).cloned();
+                let taken = room_state.take_from_box_area(&player_id, &id, Instant::now());
+                let actual = room_state.box_areas.get(&id).cloned().unwrap_or_default();
+                let changed = room_state.sessions.player_mut(&token).cloned();
+                if actual != content {
+                    server.send(
+                        &room,
+                        &player_id,
+                        &Message::UpdateBoxArea(id.clone(), actual.clone()),
+                    );
+                }
+                if let Some(changed) = changed.filter(|changed| *changed != player) {
+                    server.broadcast(&room, &player_id, &inventory(&changed));
+                }
+                if taken.is_none() || previous.as_ref() == Some(&actual) {
+                    continue;
+                }
+                if actual != BoxAreaContent::Nothing && actual != BoxAreaContent::EmptyBottle {
+                    server.metrics.box_area_spawn(&id, &actual);
+                }
+                Message::UpdateBoxArea(id, actual)
+            }
+            Message::Chat(_, text) => Message::Chat(player_id.clone(), text),
+            query @ (Message::Top(_) | Message::Rank(_)) => {
+                tokio::spawn(answer(
+                    shared.clone(),
+                    server.leaderboard.clone(),
+                    room.clone(),
+                    player_id.clone(),
+                    query,
+                ));
+                continue;
+            }
+            _ => {
+                warn!("Ignoring message '{}' of player {}", line, player_id);
+                continue;
+            }
+        };
+
+        server.broadcast(&room, &player_id, &message);
+    }
+
+    let mut server = server.lock().unwrap();
+    server.disconnect(&room, &player_id);
+    if let Some(room) = server.rooms.get_mut(&room) {
+        room.sessions
+            .disconnect(&token, chrono::Utc::now().timestamp());
+    }
+    info!("Player {} disconnected", player_id);
+}
+
+/// Returns the message announcing the inventory of a player
+fn inventory(player: &PlayerState) -> Message {
+    Message::Inventory(
+        player.id.clone(),
+        player.empty_glasses,
+        player.filled_glasses,
+        player.points,
+    )
+}
+
+/// Answers a query of the leaderboard, waiting for the database without holding the server
+async fn answer(
+    server: Arc<Mutex<Server>>,
+    leaderboard: Leaderboard,
+    room: String,
+    player_id: String,
+    query: Message,
+) {
+    let answer = match query {
+        Message::Top(count) => leaderboard
+            .top(count.clamp(1, 100))
+            .await
+            .map(Message::Leaderboard),
+        Message::Rank(name) => leaderboard.rank(&name).await.map(|ranking| {
+            let (rank, points) = ranking.unwrap_or_default();
+            Message::Ranking(name, rank, points)
+        }),
+        _ => return,
+    };
+    match answer {
+        Ok(answer) => server.lock().unwrap().send(&room, &player_id, &answer),
+        Err(e) => error!("Cannot read leaderboard: {}", e),
+    }
+}
+
+/// Rejects a connection during handshake with given reason
+async fn reject<W: AsyncWrite + Unpin>(writer: &mut W, reason: &str) {
+    let _r = writer
+        .write_all(format!("{}\n", Message::Reject(reason.to_string())).as_bytes())
+        .await;
+    let _r = writer.shutdown().await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use tokio::io::{
+        AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf, WriteHalf,
+    };
+    use tokio::sync::oneshot;
+    use winelounge::connection::Connection;
+    use winelounge::level::Level;
+    use winelounge::transport::loopback::{self, LoopbackConnector};
+
+    use crate::auth::Authenticator;
+    use crate::config::Config;
+    use crate::leaderboard::Leaderboard;
+    use crate::server::Server;
+    use crate::{serve, Layers};
+
+    /// Player connected using the loopback transport
+    struct Client {
+        id: String,
+        lines: Lines<BufReader<ReadHalf<DuplexStream>>>,
+        writer: WriteHalf<DuplexStream>,
+    }
 
-        let (socket, _) = listener.accept().await.expect("Cannot accept connection");
+    impl Client {
+        /// Joins the default room and reads all lines sent on join, up to the own inventory
+        async fn join(connector: &LoopbackConnector) -> Client {
+            let (reader, mut writer) = tokio::io::split(connector.connect().unwrap());
+            writer.write_all(b"Join\n").await.unwrap();
+            let mut lines = BufReader::new(reader).lines();
+            let welcome = lines.next_line().await.unwrap().unwrap();
+            let id = welcome.split(' ').nth(1).unwrap().to_string();
+            let mut client = Client { id, lines, writer };
+            let inventory = format!("Inventory {} 0 0 0", client.id);
+            while client.receive().await != inventory {}
+            client
+        }
 
+        async fn receive(&mut self) -> String {
+            self.lines.next_line().await.unwrap().unwrap()
+        }
     }
 
-}
\ No newline at end of file
+    /// Takes the lines received by a client connection up to the given one
+    fn receive_until(connection: &mut Connection, expected: &str) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut lines = vec![];
+        while Instant::now() < deadline {
+            match connection.try_recv() {
+                Some(line) if line == expected => return lines,
+                Some(line) => lines.push(line),
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        panic!("Did not receive {}, got {:?}", expected, lines);
+    }
+
+    #[test]
+    fn should_exchange_commands_between_players_in_one_process() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (listener, connector) = loopback::listen();
+        let server = Arc::new(Mutex::new(Server::new(
+            Config::default(),
+            None,
+            Leaderboard::in_memory(),
+        )));
+        let (stop, stopped) = oneshot::channel::<()>();
+        let serving = runtime.spawn(serve(
+            server.clone(),
+            Arc::new(Authenticator::new(None, None)),
+            Layers::default(),
+            listener,
+            async {
+                let _r = stopped.await;
+            },
+        ));
+
+        let level = Level::default();
+        let mut alice = Connection::open(connector.connect().unwrap(), vec![], &level).unwrap();
+        let mut bob = Connection::open(connector.connect().unwrap(), vec![], &level).unwrap();
+        let bob_id = bob.player_id.clone();
+        receive_until(&mut alice, &format!("Spawn {} 380 250", bob_id));
+
+        bob.send(format!("Move {} Up", bob_id));
+        bob.send(format!("Inventory {} 9 0 0", bob_id));
+        bob.send(format!("Inventory {} 1 0 2", bob_id));
+        bob.send(format!("Chat {} Cheers", bob_id));
+        assert_eq!(
+            vec![format!("Move {} Up", bob_id)],
+            receive_until(&mut alice, &format!("Chat {} Cheers", bob_id))
+        );
+        let inventory = format!("Inventory {} 0 0 0", bob_id);
+        receive_until(&mut bob, &inventory);
+        receive_until(&mut bob, &inventory);
+
+        let _r = stop.send(());
+        runtime.block_on(serving).unwrap();
+        let players = server.lock().unwrap().rooms["lounge"]
+            .sessions
+            .players()
+            .len();
+        assert_eq!(2, players);
+    }
+
+    #[tokio::test]
+    async fn should_close_connection_of_kicked_player_at_once() {
+        let (listener, connector) = loopback::listen();
+        let server = Arc::new(Mutex::new(Server::new(
+            Config::default(),
+            None,
+            Leaderboard::in_memory(),
+        )));
+        tokio::spawn(serve(
+            server.clone(),
+            Arc::new(Authenticator::new(None, None)),
+            Layers::default(),
+            listener,
+            std::future::pending(),
+        ));
+
+        let mut alice = Client::join(&connector).await;
+        assert!(server.lock().unwrap().kick("lounge", &alice.id));
+
+        assert_eq!(None, alice.lines.next_line().await.unwrap());
+        // The server stops reading without waiting for another line of the player
+        let mut closed = false;
+        for _ in 0..100 {
+            if alice.writer.write_all(b"Join\n").await.is_err() {
+                closed = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(closed);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use winelounge::net::BoxAreaContent;
+
+/// Upper bounds in seconds of the tick duration histogram buckets
+const TICK_DURATION_BUCKETS: [f64; 8] = [0.0001, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.05, 0.1];
+
+/// Server metrics to be exposed in Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    commands_received: BTreeMap<&'static str, u64>,
+    commands_sent: BTreeMap<&'static str, u64>,
+    parse_errors: u64,
+    tick_duration_buckets: [u64; TICK_DURATION_BUCKETS.len()],
+    tick_duration_sum: f64,
+    tick_duration_count: u64,
+    box_area_spawns: BTreeMap<(String, String), u64>,
+}
+
+impl Metrics {
+    pub fn command_received(&mut self, kind: &'static str) {
+        *self.commands_received.entry(kind).or_default() += 1;
+    }
+
+    pub fn command_sent(&mut self, kind: &'static str) {
+        *self.commands_sent.entry(kind).or_default() += 1;
+    }
+
+    pub fn parse_error(&mut self) {
+        self.parse_errors += 1;
+    }
+
+    pub fn tick(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        TICK_DURATION_BUCKETS
+            .iter()
+            .enumerate()
+            .filter(|(_, bound)| seconds <= **bound)
+            .for_each(|(i, _)| self.tick_duration_buckets[i] += 1);
+        self.tick_duration_sum += seconds;
+        self.tick_duration_count += 1;
+    }
+
+    /// Counts an item spawned in a box area, given by its id within the level
+    pub fn box_area_spawn(&mut self, id: &str, content: &BoxAreaContent) {
+        *self
+            .box_area_spawns
+            .entry((id.to_string(), content.to_string()))
+            .or_default() += 1;
+    }
+
+    /// Renders metrics in Prometheus text format, including the connected players per room.
+    pub fn render(&self, players_connected: &[(&str, usize)]) -> String {
+        let mut result = String::new();
+        let _r = self.write(&mut result, players_connected);
+        result
+    }
+
+    fn write(&self, f: &mut String, players_connected: &[(&str, usize)]) -> std::fmt::Result {
+        writeln!(f, "# HELP winelounge_players_connected Connected players.")?;
+        writeln!(f, "# TYPE winelounge_players_connected gauge")?;
+        for (room, players) in players_connected {
+            writeln!(
+                f,
+                "winelounge_players_connected{{room=\"{}\"}} {}",
+                room, players
+            )?;
+        }
+
+        writeln!(
+            f,
+            "# HELP winelounge_commands_received_total Commands received from clients."
+        )?;
+        writeln!(f, "# TYPE winelounge_commands_received_total counter")?;
+        for (kind, count) in &self.commands_received {
+            writeln!(
+                f,
+                "winelounge_commands_received_total{{type=\"{}\"}} {}",
+                kind, count
+            )?;
+        }
+
+        writeln!(
+            f,
+            "# HELP winelounge_commands_sent_total Commands sent to clients."
+        )?;
+        writeln!(f, "# TYPE winelounge_commands_sent_total counter")?;
+        for (kind, count) in &self.commands_sent {
+            writeln!(
+                f,
+                "winelounge_commands_sent_total{{type=\"{}\"}} {}",
+                kind, count
+            )?;
+        }
+
+        writeln!(f, "# HELP winelounge_parse_errors_total Lines received from clients that could not be parsed.")?;
+        writeln!(f, "# TYPE winelounge_parse_errors_total counter")?;
+        writeln!(f, "winelounge_parse_errors_total {}", self.parse_errors)?;
+
+        writeln!(
+            f,
+            "# HELP winelounge_tick_duration_seconds Duration of server ticks."
+        )?;
+        writeln!(f, "# TYPE winelounge_tick_duration_seconds histogram")?;
+        for (bound, count) in TICK_DURATION_BUCKETS.iter().zip(self.tick_duration_buckets) {
+            writeln!(
+                f,
+                "winelounge_tick_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            )?;
+        }
+        writeln!(
+            f,
+            "winelounge_tick_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.tick_duration_count
+        )?;
+        writeln!(
+            f,
+            "winelounge_tick_duration_seconds_sum {}",
+            self.tick_duration_sum
+        )?;
+        writeln!(
+            f,
+            "winelounge_tick_duration_seconds_count {}",
+            self.tick_duration_count
+        )?;
+
+        writeln!(
+            f,
+            "# HELP winelounge_box_area_spawns_total Items spawned in box areas."
+        )?;
+        writeln!(f, "# TYPE winelounge_box_area_spawns_total counter")?;
+        for ((id, content), count) in &self.box_area_spawns {
+            writeln!(
+                f,
+                "winelounge_box_area_spawns_total{{id=\"{}\",content=\"{}\"}} {}",
+                id, content, count
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use winelounge::net::BoxAreaContent;
+
+    use crate::metrics::Metrics;
+
+    #[test]
+    fn should_render_metrics() {
+        let mut metrics = Metrics::default();
+        metrics.command_received("Move");
+        metrics.command_received("Move");
+        metrics.command_sent("Spawn");
+        metrics.parse_error();
+        metrics.tick(Duration::from_millis(2));
+        metrics.box_area_spawn("RightTop", &BoxAreaContent::HiddenBox);
+
+        let result = metrics.render(&[("lounge", 3)]);
+
+        assert!(result.contains("winelounge_players_connected{room=\"lounge\"} 3\n"));
+        assert!(result.contains("winelounge_commands_received_total{type=\"Move\"} 2\n"));
+        assert!(result.contains("winelounge_commands_sent_total{type=\"Spawn\"} 1\n"));
+        assert!(result.contains("winelounge_parse_errors_total 1\n"));
+        assert!(result.contains("winelounge_tick_duration_seconds_bucket{le=\"0.001\"} 0\n"));
+        assert!(result.contains("winelounge_tick_duration_seconds_bucket{le=\"0.0025\"} 1\n"));
+        assert!(result.contains("winelounge_tick_duration_seconds_count 1\n"));
+        assert!(result.contains(
+            "winelounge_box_area_spawns_total{id=\"RightTop\",content=\"HiddenBox\"} 1\n"
+        ));
+    }
+}
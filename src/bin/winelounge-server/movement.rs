@@ -0,0 +1,182 @@
+use std::time::{Duration, Instant};
+
+use winelounge::geometry::{Point, Rect};
+use winelounge::level::Level;
+use winelounge::net::Direction;
+use winelounge::player::{Player, SPEED};
+use winelounge::world::TIMESTEP;
+
+use crate::session::PlayerState;
+
+/// Variation of network latency tolerated between a `Move` and the `Stop` following it
+const STOP_LATENCY: Duration = Duration::from_millis(250);
+
+/// Movement of a connected player, simulated by the server the same way clients do
+pub struct Walker {
+    player: Player,
+    /// Time the player was walked to last
+    updated_at: Instant,
+    /// Position and time of the last `Move`, the next `Stop` is checked against
+    moved: (Point, Instant),
+}
+
+impl Walker {
+    /// Starts simulating a player standing at its position
+    pub fn new(player: &PlayerState, now: Instant) -> Walker {
+        let position = Point::new(player.x, player.y);
+        Walker {
+            player: Player::spawn(&player.id, player.x.max(0) as u32, player.y.max(0) as u32),
+            updated_at: now,
+            moved: (position, now),
+        }
+    }
+
+    pub fn position(&self) -> Point {
+        self.player.position()
+    }
+
+    /// Checks if the center of the player is within given area, or was as far as it walks within
+    /// the latency tolerated
+    pub fn reaches(&self, area: &Rect) -> bool {
+        let center = self.player.center();
+        let dx = (area.x - center.x).max(center.x - area.right()).max(0);
+        let dy = (area.y - center.y).max(center.y - area.bottom()).max(0);
+        f64::hypot(dx as f64, dy as f64) <= SPEED * STOP_LATENCY.as_secs_f64()
+    }
+
+    /// Walks the player as far as it got since the last update, in steps of [TIMESTEP] like clients
+    /// do, so it slides along and stops right at stones and edges
+    pub fn update(&mut self, level: &Level, now: Instant) {
+        if self.player.moving().is_empty() {
+            self.updated_at = now;
+            return;
+        }
+        while now.saturating_duration_since(self.updated_at) >= TIMESTEP {
+            if let Some(moved) = level.walk(&self.player, TIMESTEP) {
+                self.player = moved;
+            }
+            self.updated_at += TIMESTEP;
+        }
+    }
+
+    /// Starts moving into given direction in addition to the directions moving in already
+    pub fn start_moving(&mut self, direction: Direction, level: &Level, now: Instant) {
+        self.update(level, now);
+        self.player.start_moving(direction);
+        self.moved = (self.position(), now);
+    }
+
+    /// Stops the player at given position if it could have walked there since the last `Move`,
+    /// otherwise at the position simulated.
+    ///
+    /// Returns the position the player stops at.
+    pub fn stop(&mut self, position: Point, level: &Level, now: Instant) -> Point {
+        self.update(level, now);
+        if self.could_walk_to(position, level, now) {
+            self.player = self.player.at(position);
+        }
+        self.player.stop();
+        self.position()
+    }
+
+    /// Checks if given position is off the position simulated by no more than the latency
+    /// tolerated, only along the axes moving along and without crossing stones or edges
+    fn could_walk_to(&self, position: Point, level: &Level, now: Instant) -> bool {
+        let (from, moved_at) = self.moved;
+        let reachable =
+            SPEED * (now.saturating_duration_since(moved_at) + STOP_LATENCY).as_secs_f64();
+        if f64::hypot((position.x - from.x) as f64, (position.y - from.y) as f64) > reachable {
+            return false;
+        }
+        let (vx, vy) = self.player.velocity();
+        let simulated = self.position();
+        let (dx, dy) = (position.x - simulated.x, position.y - simulated.y);
+        let distance = f64::hypot(dx as f64, dy as f64);
+        if (vx == 0.0 && dx != 0) || (vy == 0.0 && dy != 0) {
+            return false;
+        }
+        if distance > SPEED * STOP_LATENCY.as_secs_f64() {
+            return false;
+        }
+        let steps = distance.ceil() as i32;
+        (1..=steps).all(|step| {
+            let x = simulated.x + dx * step / steps;
+            let y = simulated.y + dy * step / steps;
+            level.can_stand(&self.player.at(Point::new(x, y)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use winelounge::geometry::Point;
+    use winelounge::level::{Level, HUD_HEIGHT};
+    use winelounge::net::Direction;
+
+    use crate::movement::Walker;
+    use crate::session::PlayerState;
+
+    #[test]
+    fn should_stop_at_plausible_position_only() {
+        let level = Level::default();
+        let start = Instant::now();
+        let mut walker = Walker::new(&PlayerState::spawn(None, (380, 250)), start);
+
+        walker.start_moving(Direction::Right, &level, start);
+        walker.update(&level, start + Duration::from_millis(500));
+        assert_eq!(Point::new(480, 250), walker.position());
+
+        let stopped_at = start + Duration::from_secs(1);
+        assert_eq!(
+            Point::new(570, 250),
+            walker.stop(Point::new(570, 250), &level, stopped_at)
+        );
+        assert_eq!(
+            Point::new(570, 250),
+            walker.stop(Point::new(700, 250), &level, stopped_at)
+        );
+
+        walker.start_moving(Direction::Up, &level, stopped_at);
+        let position = walker.stop(
+            Point::new(570, 10),
+            &level,
+            stopped_at + Duration::from_secs(2),
+        );
+        assert_eq!(570, position.x);
+        assert!(position.y > HUD_HEIGHT);
+    }
+
+    #[test]
+    fn should_not_stop_further_than_walked_since_last_move() {
+        let level = Level::default();
+        let start = Instant::now();
+        let mut walker = Walker::new(&PlayerState::spawn(None, (380, 250)), start);
+
+        let idle_until = start + Duration::from_secs(10);
+        assert_eq!(
+            Point::new(380, 250),
+            walker.stop(Point::new(380, 400), &level, idle_until)
+        );
+
+        walker.start_moving(Direction::Down, &level, idle_until);
+        assert_eq!(
+            Point::new(380, 250),
+            walker.stop(Point::new(380, 400), &level, idle_until)
+        );
+    }
+
+    #[test]
+    fn should_not_stop_beyond_stones() {
+        let level = Level::default();
+        let start = Instant::now();
+        let mut walker = Walker::new(&PlayerState::spawn(None, (600, 250)), start);
+
+        walker.start_moving(Direction::Right, &level, start);
+        let stopped_at = start + Duration::from_secs(1);
+        let position = walker.stop(Point::new(735, 250), &level, stopped_at);
+        assert!(position.x < 720);
+        assert_eq!(250, position.y);
+    }
+}
@@ -0,0 +1,702 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use log::{info, warn};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use winelounge::geometry::Point;
+use winelounge::level::Level;
+use winelounge::lifecycle::Lifecycle;
+use winelounge::net::{BoxAreaContent, Direction, Rules};
+use winelounge::protocol::Message;
+
+use crate::access::AccessRules;
+use crate::config::{Config, RoomConfig};
+use crate::leaderboard::Leaderboard;
+use crate::metrics::Metrics;
+use crate::movement::Walker;
+use crate::session::{PlayerState, Sessions};
+
+/// Outgoing channel of a connected player, the task writing it to the socket, the
+/// notification to stop reading from the socket once kicked and the address it connected from
+struct Client {
+    sender: UnboundedSender<String>,
+    writer: JoinHandle<()>,
+    kicked: Arc<Notify>,
+    address: IpAddr,
+}
+
+/// A room with its configuration, level, sessions, box areas and connected players
+pub struct Room {
+    pub config: RoomConfig,
+    /// Level played in this room, the default level if none
+    pub level: Option<Level>,
+    pub sessions: Sessions,
+    pub box_areas: BTreeMap<String, BoxAreaContent>,
+    /// Lifecycle of box areas, as given by the rules
+    lifecycle: Lifecycle,
+    /// Time in milliseconds the content of each box area changes on its own, if ever
+    expires_at: HashMap<String, i64>,
+    clients: HashMap<String, Client>,
+    /// Movement of connected players by player id
+    walkers: HashMap<String, Walker>,
+    /// Time in seconds since the Unix epoch the current round started at
+    pub round_started_at: i64,
+}
+
+/// Returns the lifecycle of box areas of a room: the configured one, the one of the level
+/// or the one given by the rules, in this order
+fn lifecycle(config: &RoomConfig, level: &Level) -> Lifecycle {
+    let rules = &config.rules;
+    config
+        .lifecycle
+        .clone()
+        .or_else(|| level.lifecycle.clone())
+        .unwrap_or_else(|| {
+            Lifecycle::from_rules(&Rules {
+                glass_space: rules.glass_space,
+                box_refill_after: rules.box_refill_after.into(),
+                box_clear_after: rules.box_clear_after.into(),
+            })
+        })
+}
+
+/// Returns the given level or, without, the default level
+fn level_or_default(level: &Option<Level>) -> &Level {
+    static DEFAULT_LEVEL: OnceLock<Level> = OnceLock::new();
+    match level {
+        Some(level) => level,
+        None => DEFAULT_LEVEL.get_or_init(Level::default),
+    }
+}
+
+impl Room {
+    /// Creates a new empty room
+    pub fn new(config: RoomConfig, level: Option<Level>) -> Room {
+        let lifecycle = lifecycle(&config, level_or_default(&level));
+        let mut room = Room {
+            config: RoomConfig::default(),
+            level,
+            sessions: Sessions::default(),
+            box_areas: BTreeMap::new(),
+            lifecycle,
+            expires_at: HashMap::new(),
+            clients: HashMap::new(),
+            walkers: HashMap::new(),
+            round_started_at: chrono::Utc::now().timestamp(),
+        };
+        room.configure(config);
+        room.restore_box_areas(chrono::Utc::now().timestamp_millis());
+        room
+    }
+
+    /// Restores the contents of all box areas when the level starts
+    fn restore_box_areas(&mut self, now: i64) {
+        let contents = self
+            .level()
+            .box_areas
+            .iter()
+            .map(|box_area| (box_area.id.clone(), box_area.content.clone()))
+            .collect::<Vec<_>>();
+        for (id, content) in contents {
+            self.set_box_area(&id, content, now);
+        }
+    }
+
+    /// Sets the content of a box area at given time in milliseconds, starting its timer anew.
+    ///
+    /// Returns the previous content.
+    pub fn set_box_area(
+        &mut self,
+        id: &str,
+        content: BoxAreaContent,
+        now: i64,
+    ) -> Option<BoxAreaContent> {
+        match self
+            .lifecycle
+            .expires_at(&content, now, &mut rand::thread_rng())
+        {
+            Some(expires_at) => self.expires_at.insert(id.to_string(), expires_at),
+            None => self.expires_at.remove(id),
+        };
+        self.box_areas.insert(id.to_string(), content)
+    }
+
+    /// Changes the content of box areas whose timer expired by given time in milliseconds,
+    /// as given by the lifecycle.
+    ///
+    /// Returns the ids of the box areas changed with their new content.
+    pub fn expire_box_areas(&mut self, now: i64) -> Vec<(String, BoxAreaContent)> {
+        let expired = self
+            .expires_at
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        let mut changes = vec![];
+        for id in expired {
+            let transition = self
+                .box_areas
+                .get(&id)
+                .and_then(|content| self.lifecycle.transition(content));
+            match transition.map(|transition| transition.to.clone()) {
+                Some(content) => {
+                    self.set_box_area(&id, content.clone(), now);
+                    changes.push((id, content));
+                }
+                None => {
+                    self.expires_at.remove(&id);
+                }
+            }
+        }
+        changes
+    }
+
+    /// Applies a configuration to this room. Players keep their position.
+    ///
+    /// Spawn points of the level take precedence over the configured one.
+    fn configure(&mut self, config: RoomConfig) {
+        self.sessions.spawns = match &self.level {
+            Some(level) => level
+                .spawns
+                .iter()
+                .map(|spawn| (spawn.x, spawn.y))
+                .collect(),
+            None => vec![(config.rules.spawn_x, config.rules.spawn_y)],
+        };
+        let lifecycle = lifecycle(&config, self.level());
+        if lifecycle != self.lifecycle {
+            self.lifecycle = lifecycle;
+            let now = chrono::Utc::now().timestamp_millis();
+            for (id, content) in self.box_areas.clone() {
+                self.set_box_area(&id, content, now);
+            }
+        }
+        self.config = config;
+    }
+
+    /// Returns the level played in this room
+    pub fn level(&self) -> &Level {
+        level_or_default(&self.level)
+    }
+
+    /// Checks if the level of this room has a box area with given id
+    pub fn has_box_area(&self, id: &str) -> bool {
+        self.level()
+            .box_areas
+            .iter()
+            .any(|box_area| box_area.id == id)
+    }
+
+    /// Starts simulating the movement of a connected player, standing at its position
+    pub fn enter(&mut self, player: &PlayerState, now: Instant) {
+        self.walkers
+            .insert(player.id.clone(), Walker::new(player, now));
+    }
+
+    /// Walks moving players as far as they got until now, keeping the positions of their sessions
+    /// up to date
+    pub fn walk(&mut self, now: Instant) {
+        let level = level_or_default(&self.level);
+        for (player_id, walker) in &mut self.walkers {
+            walker.update(level, now);
+            if let Some(player) = self.sessions.connected_player_mut(player_id) {
+                let position = walker.position();
+                (player.x, player.y) = (position.x, position.y);
+            }
+        }
+    }
+
+    /// Starts moving a player into given direction
+    pub fn start_moving(&mut self, player_id: &str, direction: Direction, now: Instant) {
+        let level = level_or_default(&self.level);
+        if let Some(walker) = self.walkers.get_mut(player_id) {
+            walker.start_moving(direction, level, now);
+        }
+    }
+
+    /// Stops a player at given position if plausible, see [Walker::stop].
+    ///
+    /// Returns the position the player stops at.
+    pub fn stop(&mut self, player_id: &str, position: Point, now: Instant) -> Option<Point> {
+        let level = level_or_default(&self.level);
+        let position = self.walkers.get_mut(player_id)?.stop(position, level, now);
+        let player = self.sessions.connected_player_mut(player_id)?;
+        (player.x, player.y) = (position.x, position.y);
+        Some(position)
+    }
+
+    /// Lets a player take from a box area it reached, see [winelounge::player::Player::take_from].
+    ///
+    /// Returns the content of the box area afterwards, if the player changed or revealed it.
+    pub fn take_from_box_area(
+        &mut self,
+        player_id: &str,
+        id: &str,
+        now: Instant,
+    ) -> Option<BoxAreaContent> {
+        let level = level_or_default(&self.level);
+        let area = level.box_areas.iter().find(|box_area| box_area.id == id)?;
+        let walker = self.walkers.get_mut(player_id)?;
+        walker.update(level, now);
+        if !walker.reaches(&area.rect()) {
+            return None;
+        }
+        let previous = self.box_areas.get(id)?.clone();
+        let glass_space = self.config.rules.glass_space;
+        let content = self
+            .sessions
+            .connected_player_mut(player_id)?
+            .change_inventory(|player| {
+                player.take_from(&previous, glass_space, &mut rand::thread_rng())
+            })?;
+        if content != previous {
+            self.set_box_area(id, content.clone(), chrono::Utc::now().timestamp_millis());
+        }
+        Some(content)
+    }
+
+    /// Lets a player in a lounge drink up to given number of filled glasses, as clients do.
+    ///
+    /// Returns false if the player is not in a lounge.
+    pub fn drink(&mut self, player_id: &str, glasses: u8, now: Instant) -> bool {
+        let level = level_or_default(&self.level);
+        let Some(walker) = self.walkers.get_mut(player_id) else {
+            return false;
+        };
+        walker.update(level, now);
+        if !level
+            .lounge_rects()
+            .iter()
+            .any(|lounge| walker.reaches(lounge))
+        {
+            return false;
+        }
+        let Some(player) = self.sessions.connected_player_mut(player_id) else {
+            return false;
+        };
+        player.change_inventory(|player| {
+            for _ in 0..glasses {
+                if player.can_drink_glass() {
+                    player.drink_glass();
+                }
+            }
+        });
+        true
+    }
+
+    /// Checks if the current round lasted as long as configured by given time in seconds
+    pub fn is_round_over(&self, now: i64) -> bool {
+        now >= self.round_started_at + i64::from(self.config.round_length)
+    }
+
+    /// Checks if the maximum number of players is connected
+    pub fn is_full(&self) -> bool {
+        self.clients.len() >= self.config.max_players
+    }
+
+    /// Returns the message announcing the rules of this room
+    pub fn rules(&self) -> Message {
+        let rules = &self.config.rules;
+        Message::Rules(
+            rules.glass_space,
+            rules.box_refill_after,
+            rules.box_clear_after,
+        )
+    }
+}
+
+/// Shared state of the server: configuration, access rules, rooms, leaderboard and metrics
+pub struct Server {
+    pub started_at: i64,
+    pub config: Config,
+    /// Level given on the command line, played in all rooms
+    pub level: Option<Level>,
+    pub access: AccessRules,
+    pub rooms: BTreeMap<String, Room>,
+    pub leaderboard: Leaderboard,
+    pub metrics: Metrics,
+}
+
+impl Server {
+    /// Creates a new server started now, hosting the configured rooms
+    pub fn new(config: Config, level: Option<Level>, leaderboard: Leaderboard) -> Server {
+        Server {
+            started_at: chrono::Utc::now().timestamp(),
+            rooms: config
+                .rooms
+                .iter()
+                .map(|room| (room.name.clone(), Room::new(room.clone(), level.clone())))
+                .collect(),
+            config,
+            level,
+            access: AccessRules::default(),
+            leaderboard,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Returns the room players join if they do not choose one: the first configured room
+    pub fn default_room(&self) -> &str {
+        &self.config.rooms[0].name
+    }
+
+    /// Applies a changed configuration: rules and maximum players of existing rooms and new rooms.
+    ///
+    /// Rooms removed from the configuration are kept until restart.
+    pub fn reload(&mut self, config: Config) {
+        if config.address != self.config.address
+            || config.http_address != self.config.http_address
+            || config.tick_rate != self.config.tick_rate
+        {
+            warn!("Changes of addresses and tick rate require a restart");
+        }
+        for name in self.rooms.keys() {
+            if config.room(name).is_none() {
+                warn!(
+                    "Room {} removed from configuration, kept until restart",
+                    name
+                );
+            }
+        }
+
+        for room_config in &config.rooms {
+            let name = room_config.name.clone();
+            match self.rooms.get_mut(&name) {
+                Some(room) if room.config.rules != room_config.rules => {
+                    room.configure(room_config.clone());
+                    let rules = room.rules();
+                    self.broadcast(&name, "", &rules);
+                }
+                Some(room) => room.configure(room_config.clone()),
+                None => {
+                    self.rooms
+                        .insert(name, Room::new(room_config.clone(), self.level.clone()));
+                }
+            }
+        }
+
+        self.config = Config {
+            address: self.config.address.clone(),
+            http_address: self.config.http_address.clone(),
+            tick_rate: self.config.tick_rate,
+            admin_token: self.config.admin_token.clone(),
+            ..config
+        };
+    }
+
+    /// Registers outgoing channel, kick notification and address of a connected player
+    pub fn connect(
+        &mut self,
+        room: &str,
+        player_id: &str,
+        sender: UnboundedSender<String>,
+        writer: JoinHandle<()>,
+        kicked: Arc<Notify>,
+        address: IpAddr,
+    ) {
+        if let Some(room) = self.rooms.get_mut(room) {
+            room.clients.insert(
+                player_id.to_string(),
+                Client {
+                    sender,
+                    writer,
+                    kicked,
+                    address,
+                },
+            );
+        }
+    }
+
+    /// Removes outgoing channel of a disconnected player
+    pub fn disconnect(&mut self, room: &str, player_id: &str) {
+        if let Some(room) = self.rooms.get_mut(room) {
+            room.clients.remove(player_id);
+            room.walkers.remove(player_id);
+        }
+    }
+
+    /// Sends message to one player of a room
+    pub fn send(&mut self, room: &str, player_id: &str, message: &Message) {
+        if let Some(client) = self
+            .rooms
+            .get(room)
+            .and_then(|room| room.clients.get(player_id))
+        {
+            let _r = client.sender.send(message.to_string());
+            self.metrics.command_sent(message.kind());
+        }
+    }
+
+    /// Sends message to all players of a room except the given one
+    pub fn broadcast(&mut self, room: &str, from_player_id: &str, message: &Message) {
+        let room = match self.rooms.get(room) {
+            Some(room) => room,
+            None => return,
+        };
+        for (_, client) in room
+            .clients
+            .iter()
+            .filter(|(player_id, _)| player_id.as_str() != from_player_id)
+        {
+            let _r = client.sender.send(message.to_string());
+            self.metrics.command_sent(message.kind());
+        }
+    }
+
+    /// Removes a player and closes its connection. Returns false if there is no such player.
+    pub fn kick(&mut self, room_name: &str, player_id: &str) -> bool {
+        let room = match self.rooms.get_mut(room_name) {
+            Some(room) => room,
+            None => return false,
+        };
+        let player = match room.sessions.remove(player_id) {
+            Some(player) => player,
+            None => return false,
+        };
+        if let Some(client) = room.clients.remove(player_id) {
+            client.kicked.notify_one();
+        }
+        room.walkers.remove(player_id);
+        info!(
+            "Kicked player {} with {} points from room {}",
+            player.id, player.points, room_name
+        );
+        self.broadcast(
+            room_name,
+            player_id,
+            &Message::Remove(player_id.to_string()),
+        );
+        true
+    }
+
+    /// Removes all players with a banned name or connected from an address no longer allowed
+    /// from all rooms and closes their connections
+    pub fn kick_banned(&mut self) {
+        let access = &self.access;
+        let players =
+            self.rooms
+                .iter()
+                .flat_map(|(room_name, room)| {
+                    room.sessions
+                        .players()
+                        .into_iter()
+                        .filter(|player| {
+                            player
+                                .name
+                                .as_deref()
+                                .is_some_and(|name| access.is_banned(name))
+                                || room.clients.get(&player.id).is_some_and(|client| {
+                                    !access.is_address_allowed(client.address)
+                                })
+                        })
+                        .map(|player| (room_name.clone(), player.id.clone()))
+                })
+                .collect::<Vec<_>>();
+        for (room, player_id) in players {
+            self.kick(&room, &player_id);
+        }
+    }
+
+    /// Ends the round of a room: records the scores of its players and starts the next round
+    pub fn end_round(&mut self, room_name: &str) {
+        if let Some(room) = self.rooms.get(room_name) {
+            info!("Round of room {} is over", room_name);
+            self.record_scores(room.sessions.players());
+            self.reset(room_name);
+        }
+    }
+
+    /// Starts a room over without recording scores: restores the initial box area contents and
+    /// resets players to spawn position without points, starting a new round.
+    ///
+    /// Returns false if there is no such room.
+    pub fn reset(&mut self, room_name: &str) -> bool {
+        let room = match self.rooms.get_mut(room_name) {
+            Some(room) => room,
+            None => return false,
+        };
+        room.round_started_at = chrono::Utc::now().timestamp();
+        room.restore_box_areas(chrono::Utc::now().timestamp_millis());
+        let box_areas = room
+            .box_areas
+            .iter()
+            .map(|(id, content)| Message::UpdateBoxArea(id.clone(), content.clone()))
+            .collect::<Vec<_>>();
+        room.sessions.reset_players();
+        let now = Instant::now();
+        for player in room.sessions.connected_players() {
+            room.walkers
+                .insert(player.id.clone(), Walker::new(player, now));
+        }
+
+        let players = room
+            .sessions
+            .players()
+            .into_iter()
+            .flat_map(|player| {
+                vec![
+                    Message::Spawn(player.id.clone(), player.x, player.y),
+                    Message::Inventory(
+                        player.id.clone(),
+                        player.empty_glasses,
+                        player.filled_glasses,
+                        player.points,
+                    ),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        for message in box_areas.iter().chain(players.iter()) {
+            self.broadcast(room_name, "", message);
+        }
+        true
+    }
+
+    /// Records the final scores of named players in the background
+    fn record_scores<'a>(&self, players: impl IntoIterator<Item = &'a PlayerState>) {
+        let scores = players
+            .into_iter()
+            .filter_map(|player| Some((player.name.clone()?, player.points)))
+            .collect::<Vec<_>>();
+        self.leaderboard
+            .record(scores, chrono::Utc::now().timestamp());
+    }
+
+    /// Renders metrics in Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let players_connected = self
+            .rooms
+            .iter()
+            .map(|(name, room)| (name.as_str(), room.sessions.connected_players().len()))
+            .collect::<Vec<_>>();
+        self.metrics.render(&players_connected)
+    }
+
+    /// Notifies all players about server shutdown and closes their outgoing channels.
+    ///
+    /// Returns the writer tasks, which end after remaining messages have been flushed.
+    pub fn shutdown(&mut self) -> Vec<JoinHandle<()>> {
+        let rooms = self.rooms.keys().cloned().collect::<Vec<_>>();
+        for room in &rooms {
+            self.broadcast(room, "", &Message::ServerShutdown);
+        }
+        self.rooms
+            .values_mut()
+            .flat_map(|room| room.clients.drain().map(|(_, client)| client.writer))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use tokio::sync::{mpsc, Notify};
+    use winelounge::net::{BoxAreaContent, Direction};
+
+    use crate::config::{Config, RoomConfig};
+    use crate::leaderboard::Leaderboard;
+    use crate::server::{Room, Server};
+
+    #[test]
+    fn should_change_box_areas_as_their_timers_expire() {
+        let mut room = Room::new(RoomConfig::default(), None);
+        let now = chrono::Utc::now().timestamp_millis();
+        room.set_box_area("LeftTop", BoxAreaContent::Nothing, now);
+        room.set_box_area("LeftBottom", BoxAreaContent::EmptyGlass, now);
+
+        assert!(room.expire_box_areas(now + 9_999).is_empty());
+        assert_eq!(
+            vec![("LeftTop".to_string(), BoxAreaContent::HiddenBox)],
+            room.expire_box_areas(now + 10_000)
+        );
+        assert_eq!(BoxAreaContent::HiddenBox, room.box_areas["LeftTop"]);
+
+        let changes = room.expire_box_areas(now + 40_000);
+        assert!(changes.contains(&("LeftBottom".to_string(), BoxAreaContent::Nothing)));
+    }
+
+    #[test]
+    fn should_only_let_players_take_from_box_areas_they_reached() {
+        let mut room = Room::new(RoomConfig::default(), None);
+        let now = Instant::now();
+        let (far_token, far) = room.sessions.join(None, None, 0);
+        let (near_token, mut near) = room.sessions.join(None, None, 0);
+        near.x = 700;
+        near.y = 80;
+        *room.sessions.player_mut(&near_token).unwrap() = near.clone();
+        room.enter(&far, now);
+        room.enter(&near, now);
+
+        assert_eq!(None, room.take_from_box_area(&far.id, "RightTop", now));
+        assert_eq!(BoxAreaContent::EmptyGlass, room.box_areas["RightTop"]);
+
+        assert_eq!(
+            Some(BoxAreaContent::Nothing),
+            room.take_from_box_area(&near.id, "RightTop", now)
+        );
+        assert_eq!(BoxAreaContent::Nothing, room.box_areas["RightTop"]);
+        assert_eq!(
+            1,
+            room.sessions.player_mut(&near_token).unwrap().empty_glasses
+        );
+        assert_eq!(
+            0,
+            room.sessions.player_mut(&far_token).unwrap().empty_glasses
+        );
+    }
+
+    #[test]
+    fn should_walk_players_up_to_edges_between_ticks() {
+        let mut room = Room::new(RoomConfig::default(), None);
+        let start = Instant::now();
+        let (token, mut player) = room.sessions.join(None, None, 0);
+        player.x = 560;
+        player.y = 80;
+        *room.sessions.player_mut(&token).unwrap() = player.clone();
+        room.enter(&player, start);
+
+        room.start_moving(&player.id, Direction::Right, start);
+        for seconds in 1..=3 {
+            room.walk(start + Duration::from_secs(seconds));
+        }
+
+        assert_eq!(
+            Some(BoxAreaContent::Nothing),
+            room.take_from_box_area(&player.id, "RightTop", start + Duration::from_secs(3))
+        );
+    }
+
+    #[tokio::test]
+    async fn should_kick_players_connected_from_banned_addresses() {
+        let mut server = Server::new(Config::default(), None, Leaderboard::in_memory());
+        let mut kicks = vec![];
+        for address in ["10.1.2.3", "10.2.3.4"] {
+            let room = server.rooms.get_mut("lounge").unwrap();
+            let (_, player) = room.sessions.join(None, None, 0);
+            let kicked = Arc::new(Notify::new());
+            server.connect(
+                "lounge",
+                &player.id,
+                mpsc::unbounded_channel().0,
+                tokio::spawn(async {}),
+                kicked.clone(),
+                address.parse().unwrap(),
+            );
+            kicks.push((player.id, kicked));
+        }
+
+        server.access.ban("10.1.0.0/16");
+        server.kick_banned();
+
+        let sessions = &server.rooms["lounge"].sessions;
+        assert!(!sessions.is_connected(&kicks[0].0));
+        assert!(sessions.is_connected(&kicks[1].0));
+        kicks[0].1.notified().await;
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use rand::random;
+use serde::Serialize;
+use winelounge::player::Player;
+
+/// Seconds a disconnected player is kept and can be restored by reconnecting
+pub const GRACE_PERIOD: i64 = 60;
+
+/// The player as known to the server
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PlayerState {
+    pub id: String,
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub empty_glasses: u8,
+    pub filled_glasses: u8,
+    pub points: u32,
+}
+
+impl PlayerState {
+    /// Creates a new player with random id at given spawn position.
+    pub fn spawn(name: Option<&str>, (x, y): (i32, i32)) -> PlayerState {
+        PlayerState {
+            id: random::<u32>().to_string(),
+            name: name.map(str::to_string),
+            x,
+            y,
+            empty_glasses: 0,
+            filled_glasses: 0,
+            points: 0,
+        }
+    }
+
+    /// Changes the inventory of this player by the rules of the game, as clients do
+    pub fn change_inventory<T>(&mut self, change: impl FnOnce(&mut Player) -> T) -> T {
+        let mut player = Player::spawn(&self.id, self.x.max(0) as u32, self.y.max(0) as u32);
+        player.empty_glasses = self.empty_glasses;
+        player.filled_glasses = self.filled_glasses;
+        player.points = self.points;
+        let result = change(&mut player);
+        self.empty_glasses = player.empty_glasses;
+        self.filled_glasses = player.filled_glasses;
+        self.points = player.points;
+        result
+    }
+}
+
+struct Session {
+    player: PlayerState,
+    disconnected_at: Option<i64>,
+}
+
+/// Sessions of connected and recently disconnected players, identified by their token
+pub struct Sessions {
+    sessions: HashMap<String, Session>,
+    /// Positions new and reset players are placed at, one chosen at random
+    pub spawns: Vec<(i32, i32)>,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Sessions {
+            sessions: HashMap::new(),
+            spawns: vec![(380, 250)],
+        }
+    }
+}
+
+impl Sessions {
+    /// Joins a player with its authenticated name and returns the session token and the player.
+    ///
+    /// If the given token belongs to a disconnected session of the same player name
+    /// within its grace period, the player of this session is restored.
+    /// Otherwise a new player is spawned.
+    pub fn join(
+        &mut self,
+        token: Option<&str>,
+        name: Option<&str>,
+        now: i64,
+    ) -> (String, PlayerState) {
+        if let Some(session) = token.and_then(|token| self.sessions.get_mut(token)) {
+            if let Some(disconnected_at) = session.disconnected_at {
+                if disconnected_at + GRACE_PERIOD >= now && session.player.name.as_deref() == name {
+                    session.disconnected_at = None;
+                    return (token.unwrap().to_string(), session.player.clone());
+                }
+            }
+        }
+
+        let token = format!("{:016x}", random::<u64>());
+        let player = PlayerState::spawn(name, self.spawn());
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                player: player.clone(),
+                disconnected_at: None,
+            },
+        );
+        (token, player)
+    }
+
+    /// Marks the session as disconnected. The player is kept until grace period ends.
+    pub fn disconnect(&mut self, token: &str, now: i64) {
+        if let Some(session) = self.sessions.get_mut(token) {
+            session.disconnected_at = Some(now);
+        }
+    }
+
+    /// Returns the player of a session
+    pub fn player_mut(&mut self, token: &str) -> Option<&mut PlayerState> {
+        self.sessions
+            .get_mut(token)
+            .map(|session| &mut session.player)
+    }
+
+    /// Returns the player of a connected session by its id
+    pub fn connected_player_mut(&mut self, player_id: &str) -> Option<&mut PlayerState> {
+        self.sessions
+            .values_mut()
+            .find(|session| session.player.id == player_id && session.disconnected_at.is_none())
+            .map(|session| &mut session.player)
+    }
+
+    /// Returns all players of connected sessions
+    pub fn connected_players(&self) -> Vec<&PlayerState> {
+        self.sessions
+            .values()
+            .filter(|session| session.disconnected_at.is_none())
+            .map(|session| &session.player)
+            .collect()
+    }
+
+    /// Returns all players, including those of disconnected sessions
+    pub fn players(&self) -> Vec<&PlayerState> {
+        self.sessions
+            .values()
+            .map(|session| &session.player)
+            .collect()
+    }
+
+    /// Checks if the session of a player is connected
+    pub fn is_connected(&self, player_id: &str) -> bool {
+        self.sessions
+            .values()
+            .any(|session| session.player.id == player_id && session.disconnected_at.is_none())
+    }
+
+    /// Checks if a player with given name is connected
+    pub fn is_name_connected(&self, name: &str) -> bool {
+        self.sessions.values().any(|session| {
+            session.player.name.as_deref() == Some(name) && session.disconnected_at.is_none()
+        })
+    }
+
+    /// Removes the session of a player and returns its player
+    pub fn remove(&mut self, player_id: &str) -> Option<PlayerState> {
+        let token = self
+            .sessions
+            .iter()
+            .find(|(_, session)| session.player.id == player_id)
+            .map(|(token, _)| token.clone())?;
+        self.sessions.remove(&token).map(|session| session.player)
+    }
+
+    /// Resets all players to spawn position with empty inventory and no points
+    pub fn reset_players(&mut self) {
+        let spawns = &self.spawns;
+        for session in self.sessions.values_mut() {
+            let spawn = PlayerState::spawn(
+                session.player.name.as_deref(),
+                spawns[random::<usize>() % spawns.len()],
+            );
+            session.player = PlayerState {
+                id: session.player.id.clone(),
+                ..spawn
+            };
+        }
+    }
+
+    /// Returns a random spawn position
+    fn spawn(&self) -> (i32, i32) {
+        self.spawns[random::<usize>() % self.spawns.len()]
+    }
+
+    /// Returns all sessions with their token, including disconnected ones
+    pub fn all(&self) -> Vec<(&String, &PlayerState)> {
+        self.sessions
+            .iter()
+            .map(|(token, session)| (token, &session.player))
+            .collect()
+    }
+
+    /// Restores a session as disconnected, so its player can reconnect within grace period.
+    pub fn restore(&mut self, token: &str, player: PlayerState, now: i64) {
+        self.sessions.insert(
+            token.to_string(),
+            Session {
+                player,
+                disconnected_at: Some(now),
+            },
+        );
+    }
+
+    /// Removes sessions with elapsed grace period and returns their players.
+    pub fn expire(&mut self, now: i64) -> Vec<PlayerState> {
+        let expired = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| match session.disconnected_at {
+                Some(disconnected_at) => disconnected_at + GRACE_PERIOD < now,
+                None => false,
+            })
+            .map(|(token, _)| token.clone())
+            .collect::<Vec<_>>();
+
+        expired
+            .iter()
+            .filter_map(|token| self.sessions.remove(token))
+            .map(|session| session.player)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::session::{Sessions, GRACE_PERIOD};
+
+    #[test]
+    fn should_restore_player_within_grace_period() {
+        let mut sessions = Sessions::default();
+        let (token, player) = sessions.join(None, None, 1000);
+        sessions.player_mut(&token).unwrap().points = 42;
+        sessions.disconnect(&token, 1010);
+
+        let (restored_token, restored_player) =
+            sessions.join(Some(&token), None, 1010 + GRACE_PERIOD);
+
+        assert_eq!(token, restored_token);
+        assert_eq!(player.id, restored_player.id);
+        assert_eq!(42, restored_player.points);
+    }
+
+    #[test]
+    fn should_spawn_new_player_after_grace_period() {
+        let mut sessions = Sessions::default();
+        let (token, player) = sessions.join(None, None, 1000);
+        sessions.disconnect(&token, 1010);
+
+        assert_eq!(1, sessions.expire(1011 + GRACE_PERIOD).len());
+
+        let (new_token, new_player) = sessions.join(Some(&token), None, 1011 + GRACE_PERIOD);
+        assert_ne!(token, new_token);
+        assert_ne!(player.id, new_player.id);
+    }
+
+    #[test]
+    fn should_not_restore_player_of_other_name() {
+        let mut sessions = Sessions::default();
+        let (token, player) = sessions.join(None, Some("alice"), 1000);
+        sessions.disconnect(&token, 1010);
+
+        let (other_token, other_player) = sessions.join(Some(&token), Some("bob"), 1011);
+
+        assert_ne!(token, other_token);
+        assert_ne!(player.id, other_player.id);
+        assert_eq!(Some("bob".to_string()), other_player.name);
+    }
+
+    #[test]
+    fn should_reset_players() {
+        let mut sessions = Sessions::default();
+        let (token, player) = sessions.join(None, None, 1000);
+        sessions.player_mut(&token).unwrap().y -= 15;
+        sessions.player_mut(&token).unwrap().points = 42;
+
+        sessions.reset_players();
+
+        let reset_player = sessions.player_mut(&token).unwrap();
+        assert_eq!(player.id, reset_player.id);
+        assert_eq!(player.y, reset_player.y);
+        assert_eq!(0, reset_player.points);
+    }
+
+    #[test]
+    fn should_not_restore_connected_session() {
+        let mut sessions = Sessions::default();
+        let (token, _) = sessions.join(None, None, 1000);
+
+        let (other_token, _) = sessions.join(Some(&token), None, 1001);
+        assert_ne!(token, other_token);
+    }
+}
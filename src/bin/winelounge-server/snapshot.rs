@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use log::warn;
+use winelounge::net::BoxAreaContent;
+
+use crate::server::Server;
+use crate::session::PlayerState;
+
+/// File the server state is written to on shutdown and restored from with `--restore`
+pub const SNAPSHOT_FILE: &str = "winelounge.snapshot";
+
+/// Persistable state of the server: round, box areas and sessions with players and their scores
+/// per room.
+///
+/// A snapshot is written as one line per round, per box area and per session:
+/// `Round <room> <started_at>`, `BoxArea <room> <position> <content>` and
+/// `Session <room> <token> <player_id> <x> <y> <empty_glasses> <filled_glasses> <points> [<name>]`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub rooms: BTreeMap<String, RoomSnapshot>,
+}
+
+/// Persistable state of a room
+#[derive(Debug, Default, PartialEq)]
+pub struct RoomSnapshot {
+    pub round_started_at: Option<i64>,
+    pub box_areas: BTreeMap<String, BoxAreaContent>,
+    pub sessions: Vec<(String, PlayerState)>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of current server state
+    pub fn of(server: &Server) -> Snapshot {
+        Snapshot {
+            rooms: server
+                .rooms
+                .iter()
+                .map(|(name, room)| {
+                    (
+                        name.clone(),
+                        RoomSnapshot {
+                            round_started_at: Some(room.round_started_at),
+                            box_areas: room.box_areas.clone(),
+                            sessions: room
+                                .sessions
+                                .all()
+                                .into_iter()
+                                .map(|(token, player)| (token.clone(), player.clone()))
+                                .collect(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores server state from this snapshot. Players can reconnect within grace period.
+    ///
+    /// Rooms no longer configured are skipped.
+    pub fn restore(self, server: &mut Server, now: i64) {
+        for (name, snapshot) in self.rooms {
+            let room = match server.rooms.get_mut(&name) {
+                Some(room) => room,
+                None => {
+                    warn!("Skipping snapshot of unknown room {}", name);
+                    continue;
+                }
+            };
+            if let Some(round_started_at) = snapshot.round_started_at {
+                room.round_started_at = round_started_at;
+            }
+            for (id, content) in snapshot.box_areas {
+                if room.has_box_area(&id) {
+                    room.set_box_area(&id, content, now * 1000);
+                } else {
+                    warn!(
+                        "Skipping snapshot of unknown box area {} in room {}",
+                        id, name
+                    );
+                }
+            }
+            for (token, player) in snapshot.sessions {
+                room.sessions.restore(&token, player, now);
+            }
+        }
+    }
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (room, snapshot) in &self.rooms {
+            if let Some(round_started_at) = snapshot.round_started_at {
+                writeln!(f, "Round {} {}", room, round_started_at)?;
+            }
+            for (position, content) in &snapshot.box_areas {
+                writeln!(f, "BoxArea {} {} {}", room, position, content)?;
+            }
+            for (token, player) in &snapshot.sessions {
+                write!(
+                    f,
+                    "Session {} {} {} {} {} {} {} {}",
+                    room,
+                    token,
+                    player.id,
+                    player.x,
+                    player.y,
+                    player.empty_glasses,
+                    player.filled_glasses,
+                    player.points
+                )?;
+                match &player.name {
+                    Some(name) => writeln!(f, " {}", name)?,
+                    None => writeln!(f)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut snapshot = Snapshot::default();
+
+        for line in s.lines().filter(|line| !line.is_empty()) {
+            match line.split(' ').collect::<Vec<_>>().as_slice() {
+                ["Round", room, started_at] => {
+                    snapshot
+                        .rooms
+                        .entry(room.to_string())
+                        .or_default()
+                        .round_started_at = Some(started_at.parse().map_err(|_| ())?);
+                }
+                ["BoxArea", room, position, content] => {
+                    snapshot
+                        .rooms
+                        .entry(room.to_string())
+                        .or_default()
+                        .box_areas
+                        .insert(position.to_string(), content.parse()?);
+                }
+                ["Session", room, token, player_id, x, y, empty_glasses, filled_glasses, points, name @ ..]
+                    if name.len() <= 1 =>
+                {
+                    let player = PlayerState {
+                        id: player_id.to_string(),
+                        name: name.first().map(|name| name.to_string()),
+                        x: x.parse().map_err(|_| ())?,
+                        y: y.parse().map_err(|_| ())?,
+                        empty_glasses: empty_glasses.parse().map_err(|_| ())?,
+                        filled_glasses: filled_glasses.parse().map_err(|_| ())?,
+                        points: points.parse().map_err(|_| ())?,
+                    };
+                    snapshot
+                        .rooms
+                        .entry(room.to_string())
+                        .or_default()
+                        .sessions
+                        .push((token.to_string(), player));
+                }
+                _ => return Err(()),
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use winelounge::net::BoxAreaContent;
+
+    use crate::config::Config;
+    use crate::leaderboard::Leaderboard;
+    use crate::server::Server;
+    use crate::session::PlayerState;
+    use crate::snapshot::{RoomSnapshot, Snapshot};
+
+    #[test]
+    fn should_serialize_and_deserialize_snapshot() {
+        let mut room = RoomSnapshot {
+            round_started_at: Some(1_700_000_000),
+            ..RoomSnapshot::default()
+        };
+        room.box_areas
+            .insert("RightTop".to_string(), BoxAreaContent::EmptyGlass);
+        room.sessions.push((
+            "0123456789abcdef".to_string(),
+            PlayerState {
+                id: "1234".to_string(),
+                name: Some("alice".to_string()),
+                x: 380,
+                y: 235,
+                empty_glasses: 1,
+                filled_glasses: 2,
+                points: 30,
+            },
+        ));
+        let mut snapshot = Snapshot::default();
+        snapshot.rooms.insert("lounge".to_string(), room);
+
+        let line = snapshot.to_string();
+        assert_eq!(
+            "Round lounge 1700000000\nBoxArea lounge RightTop EmptyGlass\nSession lounge 0123456789abcdef 1234 380 235 1 2 30 alice\n",
+            line
+        );
+        assert_eq!(snapshot, line.parse::<Snapshot>().unwrap());
+    }
+
+    #[test]
+    fn should_restore_box_areas_of_level_only() {
+        let mut server = Server::new(Config::default(), None, Leaderboard::in_memory());
+        let snapshot = "BoxArea lounge RightTop EmptyGlass\nBoxArea lounge Attic EmptyGlass\n"
+            .parse::<Snapshot>()
+            .unwrap();
+
+        snapshot.restore(&mut server, 1000);
+
+        let box_areas = &server.rooms["lounge"].box_areas;
+        assert_eq!(Some(&BoxAreaContent::EmptyGlass), box_areas.get("RightTop"));
+        assert_eq!(None, box_areas.get("Attic"));
+    }
+
+    #[test]
+    fn should_continue_round_of_snapshot() {
+        let mut server = Server::new(Config::default(), None, Leaderboard::in_memory());
+        let snapshot = "Round lounge 1000\n".parse::<Snapshot>().unwrap();
+
+        snapshot.restore(&mut server, 1100);
+
+        assert_eq!(1000, server.rooms["lounge"].round_started_at);
+    }
+}
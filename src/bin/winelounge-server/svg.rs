@@ -0,0 +1,184 @@
+use std::fmt::Write;
+
+use winelounge::net::BoxAreaContent;
+use winelounge::render::box_area_bushes;
+use winelounge::sprite::{PlayerDirection, PlayerFootstep, Sprite};
+
+use crate::server::Room;
+
+/// The sprite sheet, served as `/sprite.svg` and referenced by rendered worlds
+pub const SPRITE_SHEET: &str = include_str!("../../../assets/sprite.svg");
+
+/// Sprite players are shown with
+const PLAYER: Sprite = Sprite::Player(PlayerDirection::Down, PlayerFootstep::None);
+
+/// Renders an HTML page showing the world of a room, refreshing every second
+pub fn render_page(room: &Room) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta http-equiv=\"refresh\" content=\"1\">\n<title>Wine Lounge - {}</title>\n</head>\n<body style=\"background: rgb(44, 48, 63)\">\n{}</body>\n</html>\n",
+        escape(&room.config.name),
+        render(room)
+    )
+}
+
+/// Renders the world as SVG document: lounge, box areas, decoration and players with their scores
+pub fn render(room: &Room) -> String {
+    let mut result = String::new();
+    let _r = write(&mut result, room);
+    result
+}
+
+fn write(f: &mut String, room: &Room) -> std::fmt::Result {
+    let level = room.level();
+    writeln!(
+        f,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">",
+        level.width, level.height
+    )?;
+    writeln!(
+        f,
+        "<rect width=\"{}\" height=\"{}\" fill=\"rgb(206, 182, 115)\"/>",
+        level.width, level.height
+    )?;
+    writeln!(
+        f,
+        "<rect width=\"{}\" height=\"45\" fill=\"rgb(160, 90, 44)\"/>",
+        level.width
+    )?;
+
+    for lounge in &level.lounges {
+        write_sprite(f, &Sprite::Lounge, lounge.x, lounge.y)?;
+    }
+
+    for box_area in &level.box_areas {
+        let rect = box_area.rect();
+        for (sprite, x, y) in box_area_bushes(rect, &box_area.opening) {
+            write_sprite(f, &sprite, x, y)?;
+        }
+
+        if let Some(sprite) = room.box_areas.get(&box_area.id).and_then(box_sprite) {
+            let (width, height) = sprite.size();
+            write_sprite(
+                f,
+                &sprite,
+                rect.x + (rect.width as i32 - width as i32) / 2,
+                rect.y + (rect.height as i32 - height as i32) / 2,
+            )?;
+        }
+    }
+
+    for flower in &level.flowers {
+        write_sprite(f, &Sprite::Flower, flower.x, flower.y)?;
+    }
+    for stone in &level.stones {
+        write_sprite(f, &Sprite::Stone, stone.x, stone.y)?;
+    }
+
+    for player in room.sessions.connected_players() {
+        write_sprite(f, &PLAYER, player.x, player.y)?;
+        writeln!(
+            f,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-family=\"monospace\" font-size=\"12\" fill=\"rgb(44, 48, 63)\">{} ({})</text>",
+            player.x + PLAYER.size().0 as i32 / 2,
+            player.y - 4,
+            escape(player.name.as_ref().unwrap_or(&player.id)),
+            player.points
+        )?;
+    }
+
+    writeln!(f, "</svg>")
+}
+
+/// Writes a sprite as nested SVG showing the sprite rect of the sprite sheet
+fn write_sprite(f: &mut String, sprite: &Sprite, x: i32, y: i32) -> std::fmt::Result {
+    let source = sprite.rect();
+    writeln!(
+        f,
+        "<svg x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\"><image href=\"/sprite.svg\" width=\"160\" height=\"800\"/></svg>",
+        x, y, source.width, source.height, source.x, source.y, source.width, source.height
+    )
+}
+
+/// Escapes characters with special meaning in XML text and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn box_sprite(content: &BoxAreaContent) -> Option<Sprite> {
+    match content {
+        BoxAreaContent::HiddenBox => Some(Sprite::HiddenBox),
+        BoxAreaContent::FilledBottle => Some(Sprite::BottleFilled),
+        BoxAreaContent::EmptyBottle => Some(Sprite::BottleEmpty),
+        BoxAreaContent::EmptyGlass => Some(Sprite::GlassEmpty),
+        BoxAreaContent::Nothing => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use winelounge::geometry::Point;
+    use winelounge::level::Level;
+    use winelounge::net::BoxAreaContent;
+
+    use crate::config::RoomConfig;
+    use crate::server::Room;
+    use crate::svg::{render, render_page};
+
+    #[test]
+    fn should_render_box_area_content() {
+        let mut room = Room::new(RoomConfig::default(), None);
+        room.box_areas
+            .insert("LeftTop".to_string(), BoxAreaContent::HiddenBox);
+
+        let result = render(&room);
+
+        assert!(result.starts_with("<svg "));
+        assert!(result.contains(
+            "<svg x=\"35\" y=\"80\" width=\"50\" height=\"50\" viewBox=\"5 620 50 50\">"
+        ));
+    }
+
+    #[test]
+    fn should_render_level_of_room() {
+        let level = Level {
+            width: 400,
+            height: 300,
+            stones: vec![Point::new(100, 100)],
+            flowers: vec![],
+            box_areas: vec![],
+            lounges: vec![Point::new(200, 150)],
+            spawns: vec![Point::new(20, 60)],
+            lifecycle: None,
+        };
+        let room = Room::new(RoomConfig::default(), Some(level));
+
+        let result = render(&room);
+
+        assert!(result
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"400\" height=\"300\""));
+        assert!(result.contains("<svg x=\"100\" y=\"100\" width=\"25\" height=\"25\""));
+        assert!(!result.contains("<svg x=\"380\" y=\"60\""));
+        assert_eq!(vec![(20, 60)], room.sessions.spawns);
+    }
+
+    #[test]
+    fn should_escape_names() {
+        let mut room = Room::new(
+            RoomConfig {
+                name: "<lounge>".to_string(),
+                ..RoomConfig::default()
+            },
+            None,
+        );
+        room.sessions.join(None, Some("<b>\"Tom\" & Jerry</b>"), 0);
+
+        let result = render_page(&room);
+
+        assert!(result.contains("<title>Wine Lounge - &lt;lounge&gt;</title>"));
+        assert!(result.contains(">&lt;b&gt;&quot;Tom&quot; &amp; Jerry&lt;/b&gt; (0)</text>"));
+        assert!(!result.contains("<b>"));
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use winelounge::connection::fingerprint;
+
+/// Creates a TLS acceptor using the certificate chain and private key of given PEM files.
+///
+/// Returns the acceptor and the SHA-256 fingerprint of the certificate to be pinned by clients.
+pub fn acceptor(cert_file: &str, key_file: &str) -> Result<(TlsAcceptor, String), String> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(cert_file).map_err(|e| format!("Cannot open {}: {}", cert_file, e))?,
+    ))
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| format!("Cannot read certificates of {}: {}", cert_file, e))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(
+        File::open(key_file).map_err(|e| format!("Cannot open {}: {}", key_file, e))?,
+    ))
+    .map_err(|e| format!("Cannot read private key of {}: {}", key_file, e))?
+    .ok_or(format!("No private key in {}", key_file))?;
+
+    let fingerprint = certs
+        .first()
+        .map(fingerprint)
+        .ok_or(format!("No certificate in {}", cert_file))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid certificate or private key: {}", e))?;
+
+    Ok((TlsAcceptor::from(Arc::new(config)), fingerprint))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+    use winelounge::connection::fingerprint;
+
+    use crate::tls::acceptor;
+
+    #[tokio::test]
+    async fn should_accept_tls_connection_with_generated_certificate() {
+        let certified_key =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!("winelounge-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_file = dir.join("cert.pem");
+        let key_file = dir.join("key.pem");
+        std::fs::write(&cert_file, certified_key.cert.pem()).unwrap();
+        std::fs::write(&key_file, certified_key.key_pair.serialize_pem()).unwrap();
+
+        let (acceptor, server_fingerprint) =
+            acceptor(cert_file.to_str().unwrap(), key_file.to_str().unwrap()).unwrap();
+        assert_eq!(fingerprint(certified_key.cert.der()), server_fingerprint);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut stream = acceptor.accept(socket).await.unwrap();
+            stream
+                .write_all(b"Welcome 1234 0123456789abcdef\n")
+                .await
+                .unwrap();
+            stream.shutdown().await.unwrap();
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(certified_key.cert.der().clone()).unwrap();
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(
+                ServerName::try_from("localhost").unwrap(),
+                TcpStream::connect(address).await.unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let line = BufReader::new(stream).lines().next_line().await.unwrap();
+        assert_eq!(Some("Welcome 1234 0123456789abcdef".to_string()), line);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use crate::geometry::{Point, Rect};
+use crate::level::Level;
+use crate::render::{Color, Renderer};
+use crate::sprite::Sprite;
+
+/// Width of the window the world is shown in
+pub const SCREEN_WIDTH: u32 = 800;
+
+/// Height of the window the world is shown in
+pub const SCREEN_HEIGHT: u32 = 600;
+
+/// Part of the level shown on screen, given by the position of the left top corner of the screen
+/// within the level.
+///
+/// The camera stops at the edges of the level, levels smaller than the screen are shown at its left top.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Camera {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Camera {
+    /// Returns the camera showing given position in the middle of the screen, as far as the level allows
+    pub fn following(target: Point, level: &Level) -> Camera {
+        Camera {
+            x: target.x - SCREEN_WIDTH as i32 / 2,
+            y: target.y - SCREEN_HEIGHT as i32 / 2,
+        }
+        .clamped(level)
+    }
+
+    /// Returns the camera moved by given distance, as far as the level allows
+    pub fn scrolled(&self, dx: i32, dy: i32, level: &Level) -> Camera {
+        Camera {
+            x: self.x + dx,
+            y: self.y + dy,
+        }
+        .clamped(level)
+    }
+
+    /// Returns the camera moved to show no ground beyond the edges of the level
+    pub fn clamped(&self, level: &Level) -> Camera {
+        let max_x = (level.width as i32 - SCREEN_WIDTH as i32).max(0);
+        let max_y = (level.height as i32 - SCREEN_HEIGHT as i32).max(0);
+        Camera {
+            x: self.x.clamp(0, max_x),
+            y: self.y.clamp(0, max_y),
+        }
+    }
+
+    /// Converts a position on screen, e.g. of the mouse, into a position within the level
+    pub fn to_level(&self, point: Point) -> Point {
+        Point::new(point.x + self.x, point.y + self.y)
+    }
+
+    /// Returns a renderer drawing level positions at their position on screen
+    pub fn view<'a, R: Renderer>(&self, renderer: &'a mut R) -> View<'a, R> {
+        View {
+            renderer,
+            camera: *self,
+        }
+    }
+}
+
+/// Renderer drawing in level coordinates, moving everything by the position of the camera
+pub struct View<'a, R> {
+    renderer: &'a mut R,
+    camera: Camera,
+}
+
+impl<R: Renderer> Renderer for View<'_, R> {
+    fn clear(&mut self, color: Color) {
+        self.renderer.clear(color);
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let rect = Rect::new(
+            rect.x - self.camera.x,
+            rect.y - self.camera.y,
+            rect.width,
+            rect.height,
+        );
+        self.renderer.fill_rect(rect, color);
+    }
+
+    fn draw_sprite(&mut self, sprite: &Sprite, x: i32, y: i32) {
+        self.renderer
+            .draw_sprite(sprite, x - self.camera.x, y - self.camera.y);
+    }
+
+    fn draw_text(&mut self, text: &str, x: i32, y: i32) -> i32 {
+        self.renderer
+            .draw_text(text, x - self.camera.x, y - self.camera.y)
+    }
+
+    fn text_width(&self, text: &str) -> i32 {
+        self.renderer.text_width(text)
+    }
+
+    fn present(&mut self) {
+        self.renderer.present();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::camera::Camera;
+    use crate::geometry::Point;
+    use crate::level::Level;
+
+    #[test]
+    fn should_follow_target_within_level() {
+        let level = Level {
+            width: 2000,
+            height: 1000,
+            ..Level::default()
+        };
+
+        assert_eq!(
+            Camera { x: 600, y: 200 },
+            Camera::following(Point::new(1000, 500), &level)
+        );
+        assert_eq!(
+            Camera { x: 0, y: 0 },
+            Camera::following(Point::new(100, 100), &level)
+        );
+        assert_eq!(
+            Camera { x: 1200, y: 400 },
+            Camera::following(Point::new(1900, 950), &level)
+        );
+        assert_eq!(
+            Camera { x: 0, y: 0 },
+            Camera::following(Point::new(1000, 500), &Level::default())
+        );
+        assert_eq!(
+            Point::new(610, 250),
+            Camera { x: 600, y: 200 }.to_level(Point::new(10, 50))
+        );
+    }
+}
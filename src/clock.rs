@@ -0,0 +1,34 @@
+/// Source of the current time, replaceable to simulate the game without waiting
+pub trait Clock: Send {
+    /// Returns milliseconds since the Unix epoch
+    fn now_millis(&self) -> i64;
+}
+
+/// Clock using the system time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis()
+    }
+}
+
+/// Clock set to a given time, so worlds started from it start at the same time in every run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ManualClock {
+    millis: i64,
+}
+
+impl ManualClock {
+    /// Creates a clock set to given milliseconds since the Unix epoch
+    pub fn starting_at(millis: i64) -> ManualClock {
+        ManualClock { millis }
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> i64 {
+        self.millis
+    }
+}
@@ -0,0 +1,553 @@
+use std::sync::Arc;
+
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines, ReadHalf,
+};
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, Error, SignatureScheme};
+use tokio_rustls::TlsConnector;
+
+use crate::level::Level;
+use crate::protocol::Message;
+use crate::recording::Recorder;
+use crate::transport::{simulate, NetworkConditions, Stream};
+
+/// Connection to a winelounge-server.
+///
+/// Network I/O runs on a background runtime, lines are exchanged using channels
+/// to be polled by the game loop.
+pub struct Connection {
+    _runtime: Runtime,
+    sender: UnboundedSender<String>,
+    receiver: UnboundedReceiver<String>,
+    closed: bool,
+    pub player_id: String,
+    /// Session token assigned by the server, to restore the player when reconnecting
+    token: String,
+    /// Server and settings the connection was established with, if connected by address
+    endpoint: Option<Endpoint>,
+}
+
+/// Server to connect to and settings of the connection
+#[derive(Clone)]
+struct Endpoint {
+    address: String,
+    fingerprint: Option<String>,
+    conditions: Option<NetworkConditions>,
+    recording: Option<(Recorder, u64)>,
+    handshake: Vec<String>,
+    level_hash: String,
+}
+
+impl Connection {
+    /// Connects to a server, sends given handshake lines like `Login` or `Room` and joins the game.
+    ///
+    /// If a certificate fingerprint is given, TLS is used and the SHA-256 fingerprint
+    /// of the server certificate must match. This allows self-signed certificates.
+    /// If network conditions are given, lines are exchanged as if using a bad network.
+    /// If a recorder is given, all lines exchanged are recorded using the given connection id.
+    /// Joining fails if the server plays another level than the given one.
+    pub fn connect(
+        address: &str,
+        fingerprint: Option<&str>,
+        conditions: Option<NetworkConditions>,
+        recording: Option<(Recorder, u64)>,
+        handshake: Vec<String>,
+        level: &Level,
+    ) -> Result<Connection, String> {
+        let endpoint = Endpoint {
+            address: address.to_string(),
+            fingerprint: fingerprint.map(str::to_lowercase),
+            conditions,
+            recording,
+            handshake,
+            level_hash: level.hash(),
+        };
+        Self::dial(endpoint, None)
+    }
+
+    /// Connects again to the server after the connection was closed, restoring the session
+    /// if it has not expired yet. The player id may change if it has.
+    pub fn reconnect(&mut self) -> Result<(), String> {
+        let endpoint = self
+            .endpoint
+            .clone()
+            .ok_or("Cannot reconnect without server address")?;
+        *self = Self::dial(endpoint, Some(self.token.clone()))?;
+        Ok(())
+    }
+
+    fn dial(endpoint: Endpoint, token: Option<String>) -> Result<Connection, String> {
+        let runtime = runtime()?;
+        let address = endpoint.address.as_str();
+
+        let stream = runtime.block_on(async {
+            let socket = TcpStream::connect(address)
+                .await
+                .map_err(|e| format!("Cannot connect to {}: {}", address, e))?;
+
+            match &endpoint.fingerprint {
+                Some(fingerprint) => {
+                    let config = ClientConfig::builder()
+                        .dangerous()
+                        .with_custom_certificate_verifier(Arc::new(PinnedCertificate {
+                            fingerprint: fingerprint.clone(),
+                        }))
+                        .with_no_client_auth();
+                    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+                    let server_name = ServerName::try_from(host.to_string())
+                        .map_err(|e| format!("Invalid server name {}: {}", host, e))?;
+                    let stream = TlsConnector::from(Arc::new(config))
+                        .connect(server_name, socket)
+                        .await
+                        .map_err(|e| format!("TLS handshake failed: {}", e))?;
+                    Ok::<Box<dyn Stream>, String>(Box::new(stream))
+                }
+                None => Ok(Box::new(socket) as Box<dyn Stream>),
+            }
+        })?;
+        let stream = {
+            // Background tasks of wrappers run on the runtime of this connection
+            let _guard = runtime.enter();
+            let stream = match endpoint.conditions.clone() {
+                Some(conditions) => Box::new(simulate(stream, conditions)),
+                None => stream,
+            };
+            match endpoint.recording.clone() {
+                Some((recorder, connection)) => Box::new(recorder.wrap(stream, connection)),
+                None => stream,
+            }
+        };
+
+        let mut connection = Self::start(
+            runtime,
+            stream,
+            endpoint.handshake.clone(),
+            token,
+            &endpoint.level_hash,
+        )?;
+        connection.endpoint = Some(endpoint);
+        Ok(connection)
+    }
+
+    /// Joins the game over an already established stream, like an in-memory loopback connection.
+    ///
+    /// Must not be called from within an async runtime, as joining blocks until the server
+    /// welcomed the player.
+    pub fn open(
+        stream: impl Stream,
+        handshake: Vec<String>,
+        level: &Level,
+    ) -> Result<Connection, String> {
+        Self::start(runtime()?, Box::new(stream), handshake, None, &level.hash())
+    }
+
+    fn start(
+        runtime: Runtime,
+        stream: Box<dyn Stream>,
+        handshake: Vec<String>,
+        token: Option<String>,
+        level_hash: &str,
+    ) -> Result<Connection, String> {
+        let (sender, outgoing) = mpsc::unbounded_channel::<String>();
+        let (incoming, receiver) = mpsc::unbounded_channel::<String>();
+
+        let (player_id, token) = runtime.block_on(join(
+            stream, handshake, token, level_hash, outgoing, incoming,
+        ))?;
+
+        Ok(Connection {
+            _runtime: runtime,
+            sender,
+            receiver,
+            closed: false,
+            player_id,
+            token,
+            endpoint: None,
+        })
+    }
+
+    /// Sends a line to the server
+    pub fn send(&self, line: String) {
+        let _r = self.sender.send(line);
+    }
+
+    /// Returns the next line received from the server, if any
+    pub fn try_recv(&mut self) -> Option<String> {
+        match self.receiver.try_recv() {
+            Ok(line) => Some(line),
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+
+    /// Checks if the connection was closed and all lines received have been taken
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Creates the background runtime for network I/O
+fn runtime() -> Result<Runtime, String> {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Sends handshake lines and `Join` with the token of a previous session, if any,
+/// waits for `Welcome` and starts exchanging lines using the channels.
+/// Fails if the level played by the server does not have the given hash.
+///
+/// Returns the player id and the session token.
+async fn join<S>(
+    stream: S,
+    handshake: Vec<String>,
+    token: Option<String>,
+    level_hash: &str,
+    mut outgoing: UnboundedReceiver<String>,
+    incoming: UnboundedSender<String>,
+) -> Result<(String, String), String>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+
+    for line in handshake {
+        writer
+            .write_all(format!("{}\n", line).as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    writer
+        .write_all(format!("{}\n", Message::Join(token)).as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let welcome = lines
+        .next_line()
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Connection closed by server")?;
+    let (player_id, token) = match welcome.parse::<Message>() {
+        Ok(Message::Welcome(player_id, token, hash)) if hash == level_hash => (player_id, token),
+        Ok(Message::Welcome(_, _, _)) => {
+            return Err("Server plays another level, use its level file".to_string())
+        }
+        _ => return Err(welcome),
+    };
+
+    tokio::spawn(receive(lines, incoming));
+    tokio::spawn(async move {
+        while let Some(line) = outgoing.recv().await {
+            if writer
+                .write_all(format!("{}\n", line).as_bytes())
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    Ok((player_id, token))
+}
+
+async fn receive<S>(mut lines: Lines<BufReader<ReadHalf<S>>>, incoming: UnboundedSender<String>)
+where
+    S: AsyncRead,
+{
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                debug!("Received '{}'", line);
+                let _r = incoming.send(line);
+            }
+            Ok(None) => {
+                warn!("Connection closed by server");
+                return;
+            }
+            Err(e) => {
+                warn!("Connection lost: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Returns the hex encoded SHA-256 fingerprint of a certificate, as pinned by clients
+pub fn fingerprint(certificate: &CertificateDer) -> String {
+    Sha256::digest(certificate.as_ref())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Accepts the server certificate only if its SHA-256 fingerprint matches
+#[derive(Debug)]
+struct PinnedCertificate {
+    fingerprint: String,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let fingerprint = fingerprint(end_entity);
+
+        if fingerprint == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(Error::General(format!(
+                "Certificate fingerprint {} does not match",
+                fingerprint
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &ring::default_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &ring::default_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, Write};
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+    use tokio_rustls::rustls::ServerConfig;
+    use tokio_rustls::TlsAcceptor;
+
+    use crate::connection::{fingerprint, Connection};
+    use crate::level::Level;
+    use crate::transport::loopback;
+
+    /// Returns the `Welcome` line of a server playing the default level
+    fn welcome() -> String {
+        format!(
+            "Welcome 1234 0123456789abcdef {}\n",
+            Level::default().hash()
+        )
+    }
+
+    /// Starts a TLS server with a generated certificate, welcoming one client.
+    /// Returns the address and the certificate fingerprint.
+    fn start_server() -> (String, String) {
+        let certified_key =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let fingerprint = fingerprint(certified_key.cert.der());
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![certified_key.cert.der().clone()],
+                PrivateKeyDer::try_from(certified_key.key_pair.serialize_der()).unwrap(),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(async move {
+                    listener.set_nonblocking(true).unwrap();
+                    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                    loop {
+                        let (socket, _) = listener.accept().await.unwrap();
+                        if let Ok(stream) = acceptor.accept(socket).await {
+                            let (reader, mut writer) = tokio::io::split(stream);
+                            let mut lines = BufReader::new(reader).lines();
+                            if let Ok(Some(line)) = lines.next_line().await {
+                                assert_eq!("Join", line);
+                                writer.write_all(welcome().as_bytes()).await.unwrap();
+                            }
+                        }
+                    }
+                });
+        });
+
+        (address, fingerprint)
+    }
+
+    #[test]
+    fn should_connect_with_pinned_certificate() {
+        let (address, fingerprint) = start_server();
+
+        let connection = Connection::connect(
+            &address,
+            Some(&fingerprint),
+            None,
+            None,
+            vec![],
+            &Level::default(),
+        )
+        .unwrap();
+
+        assert_eq!("1234", connection.player_id);
+    }
+
+    #[test]
+    fn should_not_connect_with_other_certificate() {
+        let (address, _) = start_server();
+
+        let result = Connection::connect(
+            &address,
+            Some(&"00".repeat(32)),
+            None,
+            None,
+            vec![],
+            &Level::default(),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_restore_session_when_reconnecting() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let mut joins = vec![];
+            for _ in 0..2 {
+                let (socket, _) = listener.accept().unwrap();
+                let mut lines = std::io::BufReader::new(socket.try_clone().unwrap()).lines();
+                joins.push(lines.next().unwrap().unwrap());
+                (&socket).write_all(welcome().as_bytes()).unwrap();
+            }
+            joins
+        });
+
+        let mut connection =
+            Connection::connect(&address, None, None, None, vec![], &Level::default()).unwrap();
+        while !connection.is_closed() {
+            connection.try_recv();
+        }
+        connection.reconnect().unwrap();
+
+        assert_eq!("1234", connection.player_id);
+        assert_eq!(
+            vec!["Join", "Join 0123456789abcdef"],
+            server.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn should_join_over_loopback() {
+        let (mut listener, connector) = loopback::listen();
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(async move {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let (reader, mut writer) = tokio::io::split(stream);
+                    let mut lines = BufReader::new(reader).lines();
+                    assert_eq!(
+                        Some("Room cellar".to_string()),
+                        lines.next_line().await.unwrap()
+                    );
+                    assert_eq!(Some("Join".to_string()), lines.next_line().await.unwrap());
+                    writer
+                        .write_all(format!("{}Spawn 1234 380 250\n", welcome()).as_bytes())
+                        .await
+                        .unwrap();
+                    if let Ok(Some(line)) = lines.next_line().await {
+                        writer
+                            .write_all(format!("{}\n", line).as_bytes())
+                            .await
+                            .unwrap();
+                    }
+                });
+        });
+
+        let mut connection = Connection::open(
+            connector.connect().unwrap(),
+            vec!["Room cellar".to_string()],
+            &Level::default(),
+        )
+        .unwrap();
+        connection.send("Move 1234 Up".to_string());
+
+        let mut received = vec![];
+        while !connection.is_closed() {
+            received.extend(connection.try_recv());
+        }
+
+        assert_eq!("1234", connection.player_id);
+        assert_eq!(vec!["Spawn 1234 380 250", "Move 1234 Up"], received);
+    }
+
+    #[test]
+    fn should_not_join_server_playing_another_level() {
+        let (mut listener, connector) = loopback::listen();
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(async move {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let (reader, mut writer) = tokio::io::split(stream);
+                    let mut lines = BufReader::new(reader).lines();
+                    let _join = lines.next_line().await;
+                    writer.write_all(welcome().as_bytes()).await.unwrap();
+                });
+        });
+        let mut level = Level::default();
+        level.stones.clear();
+
+        let result = Connection::open(connector.connect().unwrap(), vec![], &level);
+
+        assert!(result.is_err());
+    }
+}
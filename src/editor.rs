@@ -0,0 +1,379 @@
+use crate::camera::Camera;
+use crate::geometry::{Point, Rect};
+use crate::level::{BoxAreaLayout, Level, MIN_BOX_AREA_SIZE};
+use crate::net::{BoxAreaContent, Direction};
+use crate::sprite::{PlayerDirection, PlayerFootstep, Sprite};
+
+/// Distance between grid lines items snap to
+pub const GRID: i32 = 10;
+
+/// Size of box areas when placed
+const BOX_AREA_SIZE: u32 = 110;
+
+/// Kind of item placed by clicking on free ground
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Stone,
+    Flower,
+    BoxArea,
+    Lounge,
+    Spawn,
+}
+
+impl Tool {
+    /// All tools in the order shown and selected by number keys
+    pub const ALL: [Tool; 5] = [
+        Tool::Stone,
+        Tool::Flower,
+        Tool::BoxArea,
+        Tool::Lounge,
+        Tool::Spawn,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Stone => "Stone",
+            Tool::Flower => "Flower",
+            Tool::BoxArea => "Box area",
+            Tool::Lounge => "Lounge",
+            Tool::Spawn => "Spawn",
+        }
+    }
+}
+
+/// Item of the edited level, given by its kind and index within the level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Item {
+    Stone(usize),
+    Flower(usize),
+    BoxArea(usize),
+    Lounge(usize),
+    Spawn(usize),
+}
+
+/// Sprite spawn points are shown with
+pub const SPAWN: Sprite = Sprite::Player(PlayerDirection::Down, PlayerFootstep::None);
+
+/// Editor of a level, placing, moving and deleting items using the mouse.
+///
+/// Items are placed at the cursor using the selected tool, dragged by holding the mouse button
+/// and snap to the grid unless turned off.
+pub struct Editor {
+    level: Level,
+    file: String,
+    tool: Tool,
+    snap: bool,
+    camera: Camera,
+    cursor: Point,
+    /// Dragged item and the offset of the cursor to its left top corner
+    dragging: Option<(Item, Point)>,
+    status: String,
+}
+
+impl Editor {
+    /// Creates an editor of given level, saved to given file
+    pub fn new(level: Level, file: &str) -> Editor {
+        Editor {
+            level,
+            file: file.to_string(),
+            tool: Tool::Stone,
+            snap: true,
+            camera: Camera::default(),
+            cursor: Point::default(),
+            dragging: None,
+            status: format!("Editing {}", file),
+        }
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+    }
+
+    pub fn snap(&self) -> bool {
+        self.snap
+    }
+
+    pub fn toggle_snap(&mut self) {
+        self.snap = !self.snap;
+    }
+
+    /// Returns the camera showing the part of the level being edited
+    pub fn camera(&self) -> Camera {
+        self.camera
+    }
+
+    /// Moves the camera by given distance, as far as the level allows
+    pub fn scroll(&mut self, dx: i32, dy: i32) {
+        self.camera = self.camera.scrolled(dx, dy, &self.level);
+    }
+
+    /// Returns the message about the last action, e.g. saving
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// Returns the area covered by an item
+    pub fn item_rect(&self, item: Item) -> Rect {
+        let at = |point: &Point, sprite: Sprite| {
+            let (width, height) = sprite.size();
+            Rect::new(point.x, point.y, width, height)
+        };
+        match item {
+            Item::Stone(index) => at(&self.level.stones[index], Sprite::Stone),
+            Item::Flower(index) => at(&self.level.flowers[index], Sprite::Flower),
+            Item::BoxArea(index) => self.level.box_areas[index].rect(),
+            Item::Lounge(index) => at(&self.level.lounges[index], Sprite::Lounge),
+            Item::Spawn(index) => at(&self.level.spawns[index], SPAWN),
+        }
+    }
+
+    /// Returns the topmost item at given position
+    pub fn item_at(&self, point: Point) -> Option<Item> {
+        let level = &self.level;
+        let items = (0..level.spawns.len())
+            .map(Item::Spawn)
+            .chain((0..level.stones.len()).map(Item::Stone))
+            .chain((0..level.flowers.len()).map(Item::Flower))
+            .chain((0..level.box_areas.len()).map(Item::BoxArea))
+            .chain((0..level.lounges.len()).map(Item::Lounge))
+            .collect::<Vec<_>>();
+        items
+            .into_iter()
+            .rev()
+            .find(|item| self.item_rect(*item).contains_point(point))
+    }
+
+    /// Starts dragging the item at given position, places a new one if there is none
+    pub fn press(&mut self, point: Point) {
+        self.cursor = point;
+        match self.item_at(point) {
+            Some(item) => {
+                let rect = self.item_rect(item);
+                self.dragging = Some((item, Point::new(point.x - rect.x, point.y - rect.y)));
+            }
+            None => self.place(self.snapped(point)),
+        }
+    }
+
+    /// Moves the cursor and the dragged item, if any
+    pub fn move_to(&mut self, point: Point) {
+        self.cursor = point;
+        if let Some((item, offset)) = self.dragging {
+            let position = self.snapped(Point::new(point.x - offset.x, point.y - offset.y));
+            self.set_position(item, position);
+        }
+    }
+
+    /// Drops the dragged item
+    pub fn release(&mut self) {
+        self.dragging = None;
+    }
+
+    /// Removes the item at the cursor
+    pub fn delete(&mut self) {
+        self.dragging = None;
+        let item = self.item_at(self.cursor);
+        let level = &mut self.level;
+        match item {
+            Some(Item::Stone(index)) => {
+                level.stones.remove(index);
+            }
+            Some(Item::Flower(index)) => {
+                level.flowers.remove(index);
+            }
+            Some(Item::BoxArea(index)) => {
+                level.box_areas.remove(index);
+            }
+            Some(Item::Lounge(index)) => {
+                level.lounges.remove(index);
+            }
+            Some(Item::Spawn(index)) => {
+                level.spawns.remove(index);
+            }
+            None => {}
+        }
+    }
+
+    /// Turns the opening of the box area at the cursor clockwise
+    pub fn rotate(&mut self) {
+        if let Some(Item::BoxArea(index)) = self.item_at(self.cursor) {
+            let box_area = &mut self.level.box_areas[index];
+            box_area.opening = match box_area.opening {
+                Direction::Up => Direction::Right,
+                Direction::Right => Direction::Down,
+                Direction::Down => Direction::Left,
+                Direction::Left => Direction::Up,
+            };
+        }
+    }
+
+    /// Grows the box area at the cursor by given number of grid steps, shrinks it if negative
+    pub fn resize(&mut self, steps: i32) {
+        if let Some(Item::BoxArea(index)) = self.item_at(self.cursor) {
+            let box_area = &mut self.level.box_areas[index];
+            let resized =
+                |size: u32| (size as i32 + steps * GRID).max(MIN_BOX_AREA_SIZE as i32) as u32;
+            box_area.width = resized(box_area.width);
+            box_area.height = resized(box_area.height);
+        }
+    }
+
+    /// Returns the level if it is playable, otherwise shows the first error
+    pub fn playable_level(&mut self) -> Option<Level> {
+        match self.level.validate() {
+            Ok(()) => Some(self.level.clone()),
+            Err(errors) => {
+                self.status = summary(&errors);
+                None
+            }
+        }
+    }
+
+    /// Writes the level to its file if it is playable
+    pub fn save(&mut self) {
+        if let Some(level) = self.playable_level() {
+            self.status = match level.save(&self.file) {
+                Ok(()) => format!("Saved {}", self.file),
+                Err(e) => e,
+            };
+        }
+    }
+
+    /// Replaces the level by the one saved in its file
+    pub fn reload(&mut self) {
+        self.dragging = None;
+        match Level::load(&self.file) {
+            Ok(level) => {
+                self.camera = self.camera.clamped(&level);
+                self.level = level;
+                self.status = format!("Loaded {}", self.file);
+            }
+            Err(e) => self.status = summary(&e),
+        }
+    }
+
+    fn place(&mut self, position: Point) {
+        let level = &mut self.level;
+        match self.tool {
+            Tool::Stone => level.stones.push(position),
+            Tool::Flower => level.flowers.push(position),
+            Tool::BoxArea => {
+                let id = (1..)
+                    .map(|n| format!("box{}", n))
+                    .find(|id| level.box_areas.iter().all(|box_area| &box_area.id != id))
+                    .unwrap();
+                level.box_areas.push(BoxAreaLayout {
+                    id,
+                    x: position.x,
+                    y: position.y,
+                    width: BOX_AREA_SIZE,
+                    height: BOX_AREA_SIZE,
+                    opening: Direction::Left,
+                    content: BoxAreaContent::Nothing,
+                });
+            }
+            Tool::Lounge => level.lounges.push(position),
+            Tool::Spawn => level.spawns.push(position),
+        }
+    }
+
+    fn set_position(&mut self, item: Item, position: Point) {
+        let level = &mut self.level;
+        match item {
+            Item::Stone(index) => level.stones[index] = position,
+            Item::Flower(index) => level.flowers[index] = position,
+            Item::BoxArea(index) => {
+                level.box_areas[index].x = position.x;
+                level.box_areas[index].y = position.y;
+            }
+            Item::Lounge(index) => level.lounges[index] = position,
+            Item::Spawn(index) => level.spawns[index] = position,
+        }
+    }
+
+    /// Returns the grid point nearest to given position, if snapping
+    fn snapped(&self, point: Point) -> Point {
+        if self.snap {
+            let snap = |value: i32| (value + GRID / 2).div_euclid(GRID) * GRID;
+            Point::new(snap(point.x), snap(point.y))
+        } else {
+            point
+        }
+    }
+}
+
+/// Returns the first line of multiple errors, mentioning how many more there are
+fn summary(errors: &str) -> String {
+    let mut lines = errors.lines();
+    let first = lines.next().unwrap_or_default();
+    match lines.count() {
+        0 => first.to_string(),
+        more => format!("{} (+{} more)", first, more),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::editor::{Editor, Item, Tool};
+    use crate::geometry::Point;
+    use crate::level::Level;
+    use crate::net::Direction;
+
+    #[test]
+    fn should_place_items_on_grid_and_drag_them() {
+        let mut editor = Editor::new(Level::default(), "level.json");
+        editor.set_tool(Tool::Flower);
+
+        editor.press(Point::new(203, 207));
+        editor.release();
+        assert_eq!(Some(&Point::new(200, 210)), editor.level().flowers.last());
+
+        editor.press(Point::new(205, 215));
+        editor.move_to(Point::new(252, 318));
+        editor.release();
+        editor.move_to(Point::new(100, 100));
+        assert_eq!(Some(&Point::new(250, 310)), editor.level().flowers.last());
+        assert_eq!(7, editor.level().flowers.len());
+    }
+
+    #[test]
+    fn should_edit_item_at_cursor() {
+        let mut editor = Editor::new(Level::default(), "level.json");
+        editor.set_tool(Tool::BoxArea);
+        editor.press(Point::new(300, 400));
+        editor.release();
+
+        assert_eq!(Some(Item::BoxArea(4)), editor.item_at(Point::new(350, 450)));
+        editor.rotate();
+        editor.resize(-10);
+        let box_area = &editor.level().box_areas[4];
+        assert_eq!("box1", box_area.id);
+        assert_eq!(Direction::Up, box_area.opening);
+        assert_eq!((60, 60), (box_area.width, box_area.height));
+
+        editor.move_to(Point::new(390, 75));
+        editor.delete();
+        assert_eq!(4, editor.level().stones.len());
+    }
+
+    #[test]
+    fn should_not_accept_unplayable_level() {
+        let mut editor = Editor::new(Level::default(), "level.json");
+        editor.move_to(Point::new(385, 255));
+        editor.delete();
+
+        assert_eq!(None, editor.playable_level());
+        assert_eq!(
+            "spawns: at least one spawn point is required",
+            editor.status()
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// A point in the world
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub const fn new(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+}
+
+/// A rectangle in the world, with its left top corner at x and y
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the x coordinate just right of this rect
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    /// Returns the y coordinate just below this rect
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    pub fn center(&self) -> Point {
+        Point::new(
+            self.x + self.width as i32 / 2,
+            self.y + self.height as i32 / 2,
+        )
+    }
+
+    /// Checks if point is within this rect, excluding its right and bottom edge
+    pub fn contains_point(&self, point: Point) -> bool {
+        point.x >= self.x && point.x < self.right() && point.y >= self.y && point.y < self.bottom()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::geometry::{Point, Rect};
+
+    #[test]
+    fn should_contain_points_within_rect() {
+        let rect = Rect::new(325, 260, 150, 95);
+
+        assert_eq!(Point::new(400, 307), rect.center());
+        assert!(rect.contains_point(Point::new(325, 260)));
+        assert!(rect.contains_point(Point::new(474, 354)));
+        assert!(!rect.contains_point(Point::new(475, 300)));
+        assert!(!rect.contains_point(Point::new(400, 355)));
+        assert!(!rect.contains_point(Point::new(324, 300)));
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::geometry::{Point, Rect};
+use crate::lifecycle::Lifecycle;
+use crate::net::{BoxAreaContent, Direction};
+use crate::player::Player;
+use crate::sprite::Sprite;
+use crate::world::BoxArea;
+
+/// Height of the HUD bar covering the top of the level
+pub const HUD_HEIGHT: i32 = 50;
+
+/// Smallest width and height of box areas
+pub const MIN_BOX_AREA_SIZE: u32 = 60;
+
+/// Layout of a world, read from a JSON file.
+///
+/// The top of the level is covered by the HUD bar and not playable.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub width: u32,
+    pub height: u32,
+    /// Stones players cannot pass
+    #[serde(default)]
+    pub stones: Vec<Point>,
+    /// Flowers only decorating the level
+    #[serde(default)]
+    pub flowers: Vec<Point>,
+    #[serde(default)]
+    pub box_areas: Vec<BoxAreaLayout>,
+    /// Lounges players drink their glasses of wine in
+    pub lounges: Vec<Point>,
+    /// Positions players are placed at when joining
+    pub spawns: Vec<Point>,
+    /// Transitions box areas go through on their own, those given by the rules if none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<Lifecycle>,
+}
+
+/// Box area of a level, surrounded by bushes except for its opening
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BoxAreaLayout {
+    /// Id box areas are addressed by in commands
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub opening: Direction,
+    /// Content when the level starts
+    #[serde(default)]
+    pub content: BoxAreaContent,
+}
+
+impl Default for Level {
+    /// Returns the level of the original game
+    fn default() -> Self {
+        serde_json::from_str(include_str!("../assets/levels/default.json"))
+            .expect("Cannot parse default level")
+    }
+}
+
+impl Level {
+    /// Reads and validates a level file
+    pub fn load(file: &str) -> Result<Level, String> {
+        let content =
+            std::fs::read_to_string(file).map_err(|e| format!("Cannot read {}: {}", file, e))?;
+        let level = serde_json::from_str::<Level>(&content)
+            .map_err(|e| format!("Cannot parse {}: {}", file, e))?;
+        level
+            .validate()
+            .map_err(|e| format!("Invalid level in {}:\n{}", file, e))?;
+        Ok(level)
+    }
+
+    /// Writes the level to a file as pretty printed JSON
+    pub fn save(&self, file: &str) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Cannot serialize level: {}", e))?;
+        std::fs::write(file, content + "\n").map_err(|e| format!("Cannot write {}: {}", file, e))
+    }
+
+    /// Returns the hex encoded SHA-256 hash of the level, for clients to check
+    /// playing the same level as the server
+    pub fn hash(&self) -> String {
+        let content = serde_json::to_string(self).expect("Cannot serialize level");
+        Sha256::digest(content.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Checks the level to be playable, returns all errors found, one per line.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = vec![];
+
+        if self.width < 200 || self.height < 200 {
+            errors.push(format!(
+                "width, height: must be at least 200x200, got {}x{}",
+                self.width, self.height
+            ));
+        }
+        let bounds = Rect::new(0, 0, self.width, self.height);
+        let mut check_within = |name: &str, items: &[Point], sprite: Sprite| {
+            for (index, item) in items.iter().enumerate() {
+                let (width, height) = sprite.size();
+                if !contains_rect(&bounds, &Rect::new(item.x, item.y, width, height)) {
+                    errors.push(format!(
+                        "{}[{}]: must be within the level, got ({}, {})",
+                        name, index, item.x, item.y
+                    ));
+                }
+            }
+        };
+        check_within("stones", &self.stones, Sprite::Stone);
+        check_within("flowers", &self.flowers, Sprite::Flower);
+        check_within("lounges", &self.lounges, Sprite::Lounge);
+        if self.lounges.is_empty() {
+            errors.push("lounges: at least one lounge is required".to_string());
+        }
+
+        let mut ids = BTreeSet::new();
+        for (index, box_area) in self.box_areas.iter().enumerate() {
+            let prefix = format!("box_areas[{}]", index);
+            if box_area.id.is_empty() || box_area.id.contains(char::is_whitespace) {
+                errors.push(format!(
+                    "{}.id: must not be empty or contain spaces, got '{}'",
+                    prefix, box_area.id
+                ));
+            } else if !ids.insert(box_area.id.as_str()) {
+                errors.push(format!("{}.id: duplicate id '{}'", prefix, box_area.id));
+            }
+            if box_area.width < MIN_BOX_AREA_SIZE || box_area.height < MIN_BOX_AREA_SIZE {
+                errors.push(format!(
+                    "{}: must be at least {}x{}, got {}x{}",
+                    prefix, MIN_BOX_AREA_SIZE, MIN_BOX_AREA_SIZE, box_area.width, box_area.height
+                ));
+            }
+            if !contains_rect(&bounds, &box_area.rect()) {
+                errors.push(format!("{}: must be within the level", prefix));
+            }
+        }
+
+        if self.spawns.is_empty() {
+            errors.push("spawns: at least one spawn point is required".to_string());
+        }
+        for (index, spawn) in self.spawns.iter().enumerate() {
+            let player = Player::spawn("", 0, 0).at(*spawn);
+            if !self.can_stand(&player) {
+                errors.push(format!(
+                    "spawns[{}]: player must fit into the playable area off any stone, got ({}, {})",
+                    index, spawn.x, spawn.y
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// Returns the part of the level not covered by the HUD bar
+    pub fn playable_rect(&self) -> Rect {
+        Rect::new(
+            0,
+            HUD_HEIGHT,
+            self.width,
+            self.height.saturating_sub(HUD_HEIGHT as u32),
+        )
+    }
+
+    /// Returns the areas covered by lounges
+    pub fn lounge_rects(&self) -> Vec<Rect> {
+        let (width, height) = Sprite::Lounge.size();
+        self.lounges
+            .iter()
+            .map(|lounge| Rect::new(lounge.x, lounge.y, width, height))
+            .collect()
+    }
+
+    /// Checks if player stands within the playable area and off any stone
+    pub fn can_stand(&self, player: &Player) -> bool {
+        !self.collides_with_stone(player) && player.within_rect(&self.playable_rect())
+    }
+
+    /// Returns the player moved by the distance walked within given time, if it moves at all.
+    ///
+    /// Players cannot walk onto stones or out of the playable area, but moving diagonally they slide along.
+    pub fn walk(&self, player: &Player, dt: Duration) -> Option<Player> {
+        let seconds = dt.as_secs_f64();
+        let (dx, dy) = match player.velocity() {
+            (vx, vy) if vx == 0.0 && vy == 0.0 => return None,
+            (vx, vy) => (vx * seconds, vy * seconds),
+        };
+        [(dx, dy), (dx, 0.0), (0.0, dy)]
+            .into_iter()
+            .filter(|(dx, dy)| *dx != 0.0 || *dy != 0.0)
+            .map(|(dx, dy)| player.moved_by(dx, dy))
+            .find(|moved| self.can_stand(moved))
+    }
+
+    /// Checks if the center of any stone is covered by player
+    pub fn collides_with_stone(&self, player: &Player) -> bool {
+        let (width, height) = Sprite::Stone.size();
+        self.stones.iter().any(|stone| {
+            let center = Point::new(stone.x + width as i32 / 2, stone.y + height as i32 / 2);
+            player.bounding_rect().contains_point(center)
+        })
+    }
+}
+
+impl BoxAreaLayout {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    /// Creates the box area of a world laid out by this level
+    pub fn box_area(&self) -> BoxArea {
+        BoxArea::new(
+            &self.id,
+            self.rect(),
+            self.opening.clone(),
+            self.content.clone(),
+        )
+    }
+}
+
+fn contains_rect(outer: &Rect, inner: &Rect) -> bool {
+    inner.x >= outer.x
+        && inner.y >= outer.y
+        && inner.right() <= outer.right()
+        && inner.bottom() <= outer.bottom()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::geometry::Point;
+    use crate::level::Level;
+
+    #[test]
+    fn should_provide_valid_default_level() {
+        let level = Level::default();
+
+        assert!(level.validate().is_ok());
+        assert_eq!(5, level.stones.len());
+        assert_eq!(4, level.box_areas.len());
+        assert_eq!(vec![Point::new(380, 250)], level.spawns);
+    }
+
+    #[test]
+    fn should_report_all_errors() {
+        let level = serde_json::from_str::<Level>(
+            r#"{
+                "width": 800,
+                "height": 600,
+                "stones": [{ "x": 380, "y": 300 }, { "x": 790, "y": 10 }],
+                "box_areas": [
+                    { "id": "cellar", "x": 10, "y": 60, "width": 100, "height": 100, "opening": "Up" },
+                    { "id": "cellar", "x": 10, "y": 300, "width": 40, "height": 100, "opening": "Down" }
+                ],
+                "lounges": [],
+                "spawns": [{ "x": 380, "y": 250 }]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Err([
+                "stones[1]: must be within the level, got (790, 10)",
+                "lounges: at least one lounge is required",
+                "box_areas[1].id: duplicate id 'cellar'",
+                "box_areas[1]: must be at least 60x60, got 40x100",
+                "spawns[0]: player must fit into the playable area off any stone, got (380, 250)",
+            ]
+            .join("\n")),
+            level.validate()
+        );
+    }
+
+    #[test]
+    fn should_hash_levels_by_content() {
+        let level = Level::default();
+        let mut other = Level::default();
+        other.stones.pop();
+
+        assert_eq!(64, level.hash().len());
+        assert_eq!(Level::default().hash(), level.hash());
+        assert_ne!(level.hash(), other.hash());
+    }
+
+    #[test]
+    fn should_reject_unknown_fields() {
+        assert!(serde_json::from_str::<Level>(
+            r#"{ "width": 800, "height": 600, "lounges": [], "spawns": [], "trees": [] }"#
+        )
+        .is_err());
+    }
+}
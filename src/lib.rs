@@ -0,0 +1,20 @@
+//! Game core of Wine Lounge, independent of rendering.
+//!
+//! Contains the world with its players and items, the commands exchanged with the server
+//! and the connection used to exchange them, so clients, bots and tools can share it.
+
+pub mod camera;
+pub mod clock;
+pub mod connection;
+pub mod editor;
+pub mod geometry;
+pub mod level;
+pub mod lifecycle;
+pub mod net;
+pub mod player;
+pub mod protocol;
+pub mod recording;
+pub mod render;
+pub mod sprite;
+pub mod transport;
+pub mod world;
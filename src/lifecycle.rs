@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::net::{BoxAreaContent, Rules};
+
+/// Change of the content of a box area after the content stayed unchanged for some time.
+///
+/// Times are given in seconds in level and configuration files.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    pub from: BoxAreaContent,
+    pub to: BoxAreaContent,
+    /// Time the content stays at least
+    #[serde(with = "seconds")]
+    pub after: Duration,
+    /// Longest random time added, drawn anew whenever a box area gets the content
+    #[serde(with = "seconds", default)]
+    pub jitter: Duration,
+}
+
+/// States box areas go through on their own, the states being their content.
+///
+/// Content without a transition stays until a player changes it.
+/// Read from level and configuration files as a list of transitions.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(transparent)]
+pub struct Lifecycle {
+    transitions: Vec<Transition>,
+}
+
+impl Lifecycle {
+    /// Creates a lifecycle using the first transition given for each content
+    pub fn new(transitions: Vec<Transition>) -> Lifecycle {
+        Lifecycle { transitions }
+    }
+
+    /// Creates the lifecycle given by the rules: empty box areas get a hidden box
+    /// and any other content is removed some time after.
+    pub fn from_rules(rules: &Rules) -> Lifecycle {
+        let refill = Transition {
+            from: BoxAreaContent::Nothing,
+            to: BoxAreaContent::HiddenBox,
+            after: Duration::from_secs(rules.box_refill_after.max(0) as u64),
+            jitter: Duration::ZERO,
+        };
+        let clear = |from| Transition {
+            from,
+            to: BoxAreaContent::Nothing,
+            after: Duration::from_secs(rules.box_clear_after.max(0) as u64),
+            jitter: Duration::from_secs(10),
+        };
+        Lifecycle::new(vec![
+            refill,
+            clear(BoxAreaContent::HiddenBox),
+            clear(BoxAreaContent::EmptyGlass),
+            clear(BoxAreaContent::FilledBottle),
+            clear(BoxAreaContent::EmptyBottle),
+        ])
+    }
+
+    /// Returns the transition leaving given content, if any
+    pub fn transition(&self, from: &BoxAreaContent) -> Option<&Transition> {
+        self.transitions
+            .iter()
+            .find(|transition| &transition.from == from)
+    }
+
+    /// Returns the time in milliseconds content set at given time changes on its own, if ever
+    pub fn expires_at(
+        &self,
+        content: &BoxAreaContent,
+        now: i64,
+        rng: &mut impl Rng,
+    ) -> Option<i64> {
+        self.transition(content).map(|transition| {
+            let jitter = rng.gen_range(0..=transition.jitter.as_millis() as i64);
+            now + transition.after.as_millis() as i64 + jitter
+        })
+    }
+}
+
+/// Reads and writes durations as whole seconds
+mod seconds {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::lifecycle::{Lifecycle, Transition};
+    use crate::net::{BoxAreaContent, Rules};
+
+    #[test]
+    fn should_refill_and_clear_box_areas_as_given_by_rules() {
+        let lifecycle = Lifecycle::from_rules(&Rules {
+            glass_space: 5,
+            box_refill_after: 5,
+            box_clear_after: 20,
+        });
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let refill = lifecycle.transition(&BoxAreaContent::Nothing).unwrap();
+        assert_eq!(BoxAreaContent::HiddenBox, refill.to);
+        assert_eq!(
+            Some(6000),
+            lifecycle.expires_at(&BoxAreaContent::Nothing, 1000, &mut rng)
+        );
+
+        let clear = lifecycle.transition(&BoxAreaContent::EmptyGlass).unwrap();
+        assert_eq!(BoxAreaContent::Nothing, clear.to);
+        assert_eq!(Duration::from_secs(20), clear.after);
+        let expires_at = lifecycle
+            .expires_at(&BoxAreaContent::EmptyGlass, 1000, &mut rng)
+            .unwrap();
+        assert!((21000..=31000).contains(&expires_at));
+    }
+
+    #[test]
+    fn should_read_transitions_in_seconds() {
+        let lifecycle = serde_json::from_str::<Lifecycle>(
+            r#"[
+                { "from": "Nothing", "to": "EmptyGlass", "after": 3 },
+                { "from": "EmptyGlass", "to": "Nothing", "after": 20, "jitter": 5 }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Lifecycle::new(vec![
+                Transition {
+                    from: BoxAreaContent::Nothing,
+                    to: BoxAreaContent::EmptyGlass,
+                    after: Duration::from_secs(3),
+                    jitter: Duration::ZERO,
+                },
+                Transition {
+                    from: BoxAreaContent::EmptyGlass,
+                    to: BoxAreaContent::Nothing,
+                    after: Duration::from_secs(20),
+                    jitter: Duration::from_secs(5),
+                },
+            ]),
+            lifecycle
+        );
+        assert!(serde_json::from_str::<Lifecycle>(
+            r#"[{ "from": "Nothing", "to": "HiddenBox", "after": 3, "every": 1 }]"#
+        )
+        .is_err());
+    }
+}
@@ -1,28 +1,66 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use sdl2::event::Event;
+use log::{error, warn};
+use sdl2::event::{Event, WindowEvent};
 use sdl2::image::LoadTexture;
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
 
-use crate::player::Player;
-use crate::world::World;
+use winelounge::camera::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use winelounge::connection::Connection;
+use winelounge::editor::{Editor, Tool, GRID};
+use winelounge::geometry::Point;
+use winelounge::level::Level;
+use winelounge::net::Direction;
+use winelounge::recording::Recorder;
+use winelounge::world::{World, TIMESTEP};
 
-mod player;
-mod sprite;
-mod world;
-mod net;
+use winelounge::render::{render_editor, render_world};
 
-const GLASS_SPACE: u8 = 5;
+use crate::sdl::SdlRenderer;
+
+mod sdl;
+
+/// Environment variable containing the password required to join the server
+const SERVER_PASSWORD_VARIABLE: &str = "WINELOUNGE_SERVER_PASSWORD";
+
+/// Environment variable containing the password of the player given using `--login`
+const PLAYER_PASSWORD_VARIABLE: &str = "WINELOUNGE_PLAYER_PASSWORD";
+
+/// File the level editor saves to unless a level is given using `--level`
+const LEVEL_FILE: &str = "level.json";
+
+/// Distance the level editor scrolls with each press of an arrow key
+const SCROLL_STEP: i32 = 5 * GRID;
+
+/// Number of players shown on the leaderboard
+const LEADERBOARD_SIZE: usize = 5;
+
+/// Most time simulated per frame, a slow frame makes the game slow down instead of stalling
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+/// Time between two rendered frames, unless rendering is slower
+const FRAME_TIME: Duration = Duration::from_millis(25);
 
 fn main() {
     simple_logger::SimpleLogger::new().env().init().unwrap();
 
+    let args = std::env::args().collect::<Vec<_>>();
+    let level = match arg_value(&args, "--level").map(|file| Level::load(file)) {
+        Some(Ok(level)) => level,
+        Some(Err(e)) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        None => Level::default(),
+    };
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
     let window = video_subsystem
-        .window("Wine Lounge", 800, 600)
+        .window("Wine Lounge", SCREEN_WIDTH, SCREEN_HEIGHT)
         .position_centered()
         .build()
         .unwrap();
@@ -39,11 +77,62 @@ fn main() {
     let font = sdl2::ttf::init().unwrap();
     let font = font.load_font("./assets/Retro.ttf", 16).unwrap();
 
+    let mut renderer = SdlRenderer {
+        canvas,
+        texture,
+        font,
+    };
+
     let mut event_pump = sdl_context.event_pump().unwrap();
 
     let mut world = World::init();
+    world.set_level(&level);
+
+    let login = arg_value(&args, "--login");
+    let mut connection = arg_value(&args, "--connect").map(|address| {
+        let mut handshake = vec![];
+        if let Ok(password) = std::env::var(SERVER_PASSWORD_VARIABLE) {
+            handshake.push(format!("Password {}", password));
+        }
+        if let Some(name) = login {
+            let password = std::env::var(PLAYER_PASSWORD_VARIABLE).unwrap_or_default();
+            handshake.push(format!("Login {} {}", name, password));
+        }
+        if let Some(room) = arg_value(&args, "--room") {
+            handshake.push(format!("Room {}", room));
+        }
+
+        let connection = Connection::connect(
+            address,
+            arg_value(&args, "--tls-fingerprint").map(String::as_str),
+            arg_value(&args, "--simulate-network")
+                .map(|conditions| conditions.parse().expect("Invalid network conditions")),
+            arg_value(&args, "--record")
+                .map(|file| (Recorder::create(file).expect("Cannot create recording"), 0)),
+            handshake,
+            &level,
+        )
+        .unwrap();
+        world.set_player_id(&connection.player_id);
+        world.follow_server(true);
+        connection
+    });
+    let level_file = arg_value(&args, "--level").map_or(LEVEL_FILE, String::as_str);
+    let mut editor = args
+        .iter()
+        .any(|arg| arg == "--edit")
+        .then(|| Editor::new(level.clone(), level_file))
+        .filter(|_| connection.is_none());
+    let mut leaderboard_requested_at: Option<Instant> = None;
+    let mut show_leaderboard = false;
+    let mut previous_frame_at = Instant::now();
+    let mut accumulator = Duration::ZERO;
 
     'running: loop {
+        let frame_at = Instant::now();
+        accumulator += (frame_at - previous_frame_at).min(MAX_FRAME_TIME);
+        previous_frame_at = frame_at;
+
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
@@ -53,16 +142,181 @@ fn main() {
                 } => {
                     break 'running;
                 }
-                e => world.handle_event(e),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } if connection.is_some() => {
+                    // The server plays its own level, changing it here would only confuse both
+                    warn!("Cannot edit the level while connected to a server");
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => match &mut editor {
+                    Some(e) => {
+                        if let Some(level) = e.playable_level() {
+                            world.set_level(&level);
+                            editor = None;
+                        }
+                    }
+                    None => {
+                        world.stop_player();
+                        editor = Some(Editor::new(world.level().clone(), level_file));
+                    }
+                },
+                e => match &mut editor {
+                    Some(editor) => handle_editor_event(editor, e),
+                    None => handle_event(&mut world, e, &mut show_leaderboard),
+                },
             }
         }
 
-        if chrono::Utc::now().timestamp_millis() % 1000 > 950 {
-            world.update_box_areas();
+        while accumulator >= TIMESTEP {
+            world.update(TIMESTEP);
+            accumulator -= TIMESTEP;
         }
 
-        world.render(&mut canvas, &texture, &font);
+        if let Some(connection) = &mut connection {
+            if leaderboard_requested_at.is_none_or(|at| at.elapsed() > Duration::from_secs(10)) {
+                connection.send(format!("Top {}", LEADERBOARD_SIZE));
+                if let Some(name) = login {
+                    connection.send(format!("Rank {}", name));
+                }
+                leaderboard_requested_at = Some(Instant::now());
+            }
+            for command in world.take_outgoing() {
+                connection.send(command.to_string());
+            }
+            while let Some(line) = connection.try_recv() {
+                if let Ok(command) = line.parse() {
+                    world.execute_command(command);
+                }
+            }
+        }
+        if let Some(closed) = connection.as_mut().filter(|connection| connection.is_closed()) {
+            // Resume the session using its token, e.g. after the network dropped
+            match closed.reconnect() {
+                Ok(()) => world.set_player_id(&closed.player_id),
+                Err(e) => {
+                    error!("Cannot reconnect: {}", e);
+                    connection = None;
+                    world.follow_server(false);
+                }
+            }
+        }
+
+        match &editor {
+            Some(editor) => render_editor(&mut renderer, editor),
+            None => render_world(&mut renderer, &world, show_leaderboard),
+        }
+
+        ::std::thread::sleep(FRAME_TIME.saturating_sub(frame_at.elapsed()));
+    }
+}
+
+/// Handles key events for player move.
+///
+/// The player moves while arrow keys are held and stops if the window loses focus,
+/// as keys released elsewhere are not noticed. The leaderboard is shown while the tab key is pressed.
+fn handle_event(world: &mut World, event: Event, show_leaderboard: &mut bool) {
+    match event {
+        Event::KeyDown {
+            keycode: Some(Keycode::Tab),
+            ..
+        } => *show_leaderboard = true,
+        Event::KeyUp {
+            keycode: Some(Keycode::Tab),
+            ..
+        } => *show_leaderboard = false,
+        Event::KeyDown {
+            keycode: Some(keycode),
+            repeat: false,
+            ..
+        } => {
+            if let Some(direction) = direction(keycode) {
+                world.start_moving(direction)
+            }
+        }
+        Event::KeyUp {
+            keycode: Some(keycode),
+            ..
+        } => {
+            if let Some(direction) = direction(keycode) {
+                world.stop_moving(&direction)
+            }
+        }
+        Event::Window {
+            win_event: WindowEvent::FocusLost,
+            ..
+        } => world.stop_player(),
+        _ => {}
+    }
+}
+
+/// Handles mouse and key events for editing the level.
+///
+/// Clicking places an item of the selected tool or drags the item clicked on, right clicking deletes it.
+/// The mouse wheel resizes box areas, number keys select the tool and arrow keys scroll larger levels.
+fn handle_editor_event(editor: &mut Editor, event: Event) {
+    let camera = editor.camera();
+    match event {
+        Event::MouseButtonDown {
+            mouse_btn: MouseButton::Left,
+            x,
+            y,
+            ..
+        } => editor.press(camera.to_level(Point::new(x, y))),
+        Event::MouseButtonDown {
+            mouse_btn: MouseButton::Right,
+            x,
+            y,
+            ..
+        } => {
+            editor.move_to(camera.to_level(Point::new(x, y)));
+            editor.delete()
+        }
+        Event::MouseButtonUp {
+            mouse_btn: MouseButton::Left,
+            ..
+        } => editor.release(),
+        Event::MouseMotion { x, y, .. } => editor.move_to(camera.to_level(Point::new(x, y))),
+        Event::MouseWheel { y, .. } => editor.resize(y),
+        Event::KeyDown {
+            keycode: Some(keycode),
+            ..
+        } => match keycode {
+            Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5 => {
+                let index = keycode as i32 - Keycode::Num1 as i32;
+                editor.set_tool(Tool::ALL[index as usize])
+            }
+            Keycode::Up => editor.scroll(0, -SCROLL_STEP),
+            Keycode::Down => editor.scroll(0, SCROLL_STEP),
+            Keycode::Left => editor.scroll(-SCROLL_STEP, 0),
+            Keycode::Right => editor.scroll(SCROLL_STEP, 0),
+            Keycode::Delete | Keycode::Backspace => editor.delete(),
+            Keycode::R => editor.rotate(),
+            Keycode::G => editor.toggle_snap(),
+            Keycode::S => editor.save(),
+            Keycode::L => editor.reload(),
+            _ => {}
+        },
+        _ => {}
+    }
+}
 
-        ::std::thread::sleep(Duration::from_millis(25));
+/// Returns the direction an arrow or WASD key moves the player into
+fn direction(keycode: Keycode) -> Option<Direction> {
+    match keycode {
+        Keycode::Up | Keycode::W => Some(Direction::Up),
+        Keycode::Down | Keycode::S => Some(Direction::Down),
+        Keycode::Left | Keycode::A => Some(Direction::Left),
+        Keycode::Right | Keycode::D => Some(Direction::Right),
+        _ => None,
     }
 }
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+}
@@ -1,7 +1,87 @@
-use crate::world::{BoxAreaContent, BoxAreaPosition, Command, Direction};
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::Message;
+
+/// Number of glasses a player can carry, unless the server sends other rules
+pub const GLASS_SPACE: u8 = 5;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    SpawnPlayer(String, u32, u32),
+    RemovePlayer(String),
+    FacePlayer(String, Direction),
+    /// Player starts moving into direction, in addition to directions moving in already
+    MovePlayer(String, Direction),
+    /// Player stops moving into any direction at given position
+    StopPlayer(String, u32, u32),
+    UpdateInventory(String, u8, u8, u32),
+    UpdateLeaderboard(Vec<(String, u32)>),
+    UpdateRanking(String, usize, u32),
+    UpdateRules(Rules),
+    /// Box area with given id gets given content
+    UpdateBoxArea(String, BoxAreaContent),
+}
+
+/// Game rules, as sent by the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules {
+    /// Number of glasses a player can carry
+    pub glass_space: u8,
+    /// Seconds until an empty box area gets a new hidden box
+    pub box_refill_after: i64,
+    /// Seconds until the content of a box area is removed
+    pub box_clear_after: i64,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            glass_space: GLASS_SPACE,
+            box_refill_after: 10,
+            box_clear_after: 30,
+        }
+    }
+}
+
+/// Content of a BoxArea
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BoxAreaContent {
+    #[default]
+    Nothing,
+    HiddenBox,
+    EmptyGlass,
+    FilledBottle,
+    EmptyBottle,
+}
+
+impl BoxAreaContent {
+    /// Selects new random BoxAreaContent
+    pub fn random() -> BoxAreaContent {
+        Self::random_using(&mut rand::thread_rng())
+    }
+
+    /// Selects new random BoxAreaContent using given random number generator
+    pub fn random_using(rng: &mut impl Rng) -> BoxAreaContent {
+        match rng.gen::<i32>() % 5 {
+            1 | 4 => BoxAreaContent::EmptyGlass,
+            2 | 3 => BoxAreaContent::FilledBottle,
+            _ => BoxAreaContent::Nothing,
+        }
+    }
+}
+
 impl Display for Direction {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -27,31 +107,6 @@ impl FromStr for Direction {
     }
 }
 
-impl Display for BoxAreaPosition {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            BoxAreaPosition::RightTop => write!(f, "RightTop"),
-            BoxAreaPosition::RightBottom => write!(f, "RightBottom"),
-            BoxAreaPosition::LeftBottom => write!(f, "LeftBottom"),
-            BoxAreaPosition::LeftTop => write!(f, "LeftTop"),
-        }
-    }
-}
-
-impl FromStr for BoxAreaPosition {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "RightTop" => Ok(BoxAreaPosition::RightTop),
-            "RightBottom" => Ok(BoxAreaPosition::RightBottom),
-            "LeftBottom" => Ok(BoxAreaPosition::LeftBottom),
-            "LeftTop" => Ok(BoxAreaPosition::LeftTop),
-            _ => Err(()),
-        }
-    }
-}
-
 impl Display for BoxAreaContent {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -81,14 +136,7 @@ impl FromStr for BoxAreaContent {
 
 impl Display for Command {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Command::SpawnPlayer(player_id, _x, _y) => write!(f, "Spawn {}", player_id),
-            Command::RemovePlayer(player_id) => write!(f, "Face {}", player_id),
-            Command::FacePlayer(player_id, direction) => write!(f, "Face {} {}", player_id, direction),
-            Command::MovePlayer(player_id, direction) => write!(f, "Move {} {}", player_id, direction),
-            Command::StopPlayer(player_id) => write!(f, "Stop {}", player_id),
-            Command::UpdateBoxArea(pos, content) => write!(f, "UpdateBoxArea {} {}", pos, content),
-        }
+        write!(f, "{}", Message::from(self))
     }
 }
 
@@ -96,63 +144,14 @@ impl FromStr for Command {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split(' ');
-
-        match parts.next() {
-            Some("Spawn") => match parts.next() {
-                Some(player_id) => match parts.next() {
-                    Some(x) => match parts.next() {
-                        Some(y) => Ok(Command::SpawnPlayer(player_id.to_string(), x.parse().unwrap(), y.parse().unwrap())),
-                        _ => Err(())
-                    },
-                    _ => Err(())
-                }
-                _ => Err(())
-            },
-            Some("Remove") => match parts.next() {
-                Some(player_id) => Ok(Command::RemovePlayer(player_id.to_string())),
-                _ => Err(())
-            },
-            Some("Face") => match parts.next() {
-                Some(player_id) => match parts.next() {
-                    Some(direction) => Ok(Command::FacePlayer(player_id.to_string(), direction.parse::<Direction>().unwrap())),
-                    _ => Err(()),
-                },
-                _ => Err(())
-            },
-            Some("Move") => match parts.next() {
-                Some(player_id) => match parts.next() {
-                    Some(direction) => Ok(Command::MovePlayer(player_id.to_string(), direction.parse::<Direction>().unwrap())),
-                    _ => Err(()),
-                },
-                _ => Err(())
-            },
-            Some("Stop") => match parts.next() {
-                Some(player_id) => Ok(Command::StopPlayer(player_id.to_string())),
-                _ => Err(())
-            },
-            Some("UpdateBoxArea") => match parts.next() {
-                Some(position) => {
-                    let position = position.parse::<BoxAreaPosition>().unwrap();
-                    match parts.next() {
-                        Some(content) => {
-                            let content = content.parse::<BoxAreaContent>().unwrap();
-                            Ok(Command::UpdateBoxArea(position, content))
-                        }
-                        _ => Err(()),
-                    }
-                }
-                _ => Err(()),
-            },
-            Some(_) | None => Err(()),
-        }
+        Command::try_from(s.parse::<Message>()?)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::world::Direction::{Left, Up};
-    use crate::world::{BoxAreaContent, BoxAreaPosition, Command};
+    use crate::net::Direction::{Left, Up};
+    use crate::net::{BoxAreaContent, Command, Rules};
 
     #[test]
     fn should_deserialize_command_line() {
@@ -172,9 +171,32 @@ mod test {
             Command::MovePlayer("1234".to_string(), Up),
             "Move 1234 Up".parse::<Command>().unwrap()
         );
-        assert_eq!(Command::StopPlayer("1234".to_string()), "Stop 1234".parse::<Command>().unwrap());
         assert_eq!(
-            Command::UpdateBoxArea(BoxAreaPosition::RightBottom, BoxAreaContent::HiddenBox),
+            Command::StopPlayer("1234".to_string(), 100, 200),
+            "Stop 1234 100 200".parse::<Command>().unwrap()
+        );
+        assert_eq!(
+            Command::UpdateInventory("1234".to_string(), 1, 2, 30),
+            "Inventory 1234 1 2 30".parse::<Command>().unwrap()
+        );
+        assert_eq!(
+            Command::UpdateLeaderboard(vec![("alice".to_string(), 50), ("bob".to_string(), 30)]),
+            "Leaderboard alice:50 bob:30".parse::<Command>().unwrap()
+        );
+        assert_eq!(
+            Command::UpdateRanking("alice".to_string(), 1, 50),
+            "Ranking alice 1 50".parse::<Command>().unwrap()
+        );
+        assert_eq!(
+            Command::UpdateRules(Rules {
+                glass_space: 3,
+                box_refill_after: 5,
+                box_clear_after: 20
+            }),
+            "Rules 3 5 20".parse::<Command>().unwrap()
+        );
+        assert_eq!(
+            Command::UpdateBoxArea("RightBottom".to_string(), BoxAreaContent::HiddenBox),
             "UpdateBoxArea RightBottom HiddenBox"
                 .parse::<Command>()
                 .unwrap()
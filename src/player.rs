@@ -1,21 +1,34 @@
-use rand::random;
+use rand::Rng;
+use crate::geometry::{Point, Rect};
+use crate::net::{BoxAreaContent, Direction};
 use crate::sprite::Sprite;
-use crate::{sprite, GLASS_SPACE};
-use sdl2::rect::{Point, Rect};
-use sdl2::render::{Texture, WindowCanvas};
+use crate::sprite;
+
+/// Pixels per second a moving player walks, diagonally as well
+pub const SPEED: f64 = 200.0;
+
+/// Pixels a player walks before doing the next footstep
+const FOOTSTEP_DISTANCE: f64 = 15.0;
 
 /// The player with his position, direction ...
+#[derive(Clone)]
 pub struct Player {
     pub id: String,
-    position: Point,
+    x: f64,
+    y: f64,
     direction: PlayerDirection,
+    /// Directions the player is moving in, like arrow keys held down
+    moving: Vec<Direction>,
     footstep: u8,
+    /// Pixels walked since the last footstep
+    walked: f64,
     pub empty_glasses: u8,
     pub filled_glasses: u8,
     pub points: u32,
 }
 
 /// Player only can turn in 90deg angle
+#[derive(Clone)]
 enum PlayerDirection {
     Up,
     Down,
@@ -24,26 +37,35 @@ enum PlayerDirection {
 }
 
 impl Player {
-    /// Initializes Player with fixed position and direction.
-    pub fn init() -> Player {
-        Self::spawn(random::<u32>().to_string().as_str(), 380, 250)
+    /// Initializes Player with fixed position and direction and a random id.
+    pub fn init(rng: &mut impl Rng) -> Player {
+        Self::spawn(rng.gen::<u32>().to_string().as_str(), 380, 250)
     }
 
     pub fn spawn(player_id: &str, x: u32, y: u32) -> Player {
         Player {
             id: player_id.to_string(),
-            position: Point::new(x as i32, y as i32),
+            x: x as f64,
+            y: y as f64,
             direction: PlayerDirection::Down,
+            moving: vec![],
             footstep: 0,
+            walked: 0.0,
             empty_glasses: 0,
             filled_glasses: 0,
             points: 0,
         }
     }
 
-    /// Checks, if player can pick a glass or if inventory is full
-    pub fn can_pick_glass(&self) -> bool {
-        self.empty_glasses + self.filled_glasses < GLASS_SPACE
+    /// Places player at given position
+    pub fn set_position(&mut self, x: u32, y: u32) {
+        self.x = x as f64;
+        self.y = y as f64;
+    }
+
+    /// Checks, if player can pick a glass or if inventory with given space is full
+    pub fn can_pick_glass(&self, glass_space: u8) -> bool {
+        self.empty_glasses + self.filled_glasses < glass_space
     }
 
     /// Make player pick a glass
@@ -75,30 +97,63 @@ impl Player {
         self.points += 5
     }
 
+    /// Takes what the player can from a box area with given content: hidden boxes are opened,
+    /// empty glasses picked and glasses filled from bottles.
+    ///
+    /// Returns the content of the box area afterwards, if the player changed or revealed it.
+    pub fn take_from(&mut self, content: &BoxAreaContent, glass_space: u8, rng: &mut impl Rng) -> Option<BoxAreaContent> {
+        let content = match content {
+            BoxAreaContent::HiddenBox => BoxAreaContent::random_using(rng),
+            content => content.clone(),
+        };
+        match content {
+            BoxAreaContent::EmptyGlass if self.can_pick_glass(glass_space) => {
+                self.pick_glass();
+                Some(BoxAreaContent::Nothing)
+            }
+            BoxAreaContent::FilledBottle if self.can_fill_glass() => {
+                self.fill_glass();
+                Some(BoxAreaContent::EmptyBottle)
+            }
+            BoxAreaContent::EmptyGlass | BoxAreaContent::FilledBottle => Some(content),
+            _ => None,
+        }
+    }
+
     pub fn center(&self) -> Point {
         self.bounding_rect().center()
     }
 
     pub fn bounding_rect(&self) -> Rect {
+        let position = self.position();
         Rect::new(
-            self.position.x(),
-            self.position.y(),
-            self.sprite().rect().width(),
-            self.sprite().rect().height(),
+            position.x,
+            position.y,
+            self.sprite().rect().width,
+            self.sprite().rect().height,
         )
     }
 
     pub fn within_rect(&self, rect: &Rect) -> bool {
-        self.position.y > rect.y()
-            && self.position.y < rect.y() + (rect.height() - self.bounding_rect().height()) as i32
-            && self.position.x > rect.x()
-            && self.position.x < (rect.width() - self.bounding_rect().width()) as i32
+        let position = self.position();
+        position.y > rect.y
+            && position.y < rect.y + (rect.height - self.bounding_rect().height) as i32
+            && position.x > rect.x
+            && position.x < (rect.width - self.bounding_rect().width) as i32
+    }
+
+    /// Returns a copy of this player placed at given position
+    pub fn at(&self, position: Point) -> Player {
+        Player {
+            x: position.x as f64,
+            y: position.y as f64,
+            ..self.clone()
+        }
     }
 
-    /// Renders player
-    pub fn render(&self, canvas: &mut WindowCanvas, texture: &Texture) {
-        self.sprite()
-            .render(canvas, texture, self.position.x(), self.position.y());
+    /// Returns the position of the left top corner of the player, rounded to whole pixels
+    pub fn position(&self) -> Point {
+        Point::new(self.x.round() as i32, self.y.round() as i32)
     }
 
     pub fn face_up(&mut self) {
@@ -117,35 +172,78 @@ impl Player {
         self.direction = PlayerDirection::Right;
     }
 
-    pub fn move_up(&mut self) {
-        self.face_up();
-        self.footstep = &self.footstep % 2 + 1;
-        self.position.y -= 15
-    }
-
-    pub fn move_down(&mut self) {
-        self.face_down();
-        self.footstep = &self.footstep % 2 + 1;
-        self.position.y += 15
+    fn face(&mut self, direction: &Direction) {
+        match direction {
+            Direction::Down => self.face_down(),
+            Direction::Up => self.face_up(),
+            Direction::Left => self.face_left(),
+            Direction::Right => self.face_right(),
+        }
     }
 
-    pub fn move_left(&mut self) {
-        self.face_left();
-        self.footstep = &self.footstep % 2 + 1;
-        self.position.x -= 15
+    /// Starts moving into given direction in addition to the directions moving in already.
+    ///
+    /// Moving up or down and left or right at the same time moves the player diagonally.
+    pub fn start_moving(&mut self, direction: Direction) {
+        self.face(&direction);
+        if !self.moving.contains(&direction) {
+            self.moving.push(direction);
+        }
     }
 
-    pub fn move_right(&mut self) {
-        self.face_right();
-        self.footstep = &self.footstep % 2 + 1;
-        self.position.x += 15
+    /// Stops moving into given direction, but keeps moving into any other direction
+    pub fn stop_moving(&mut self, direction: &Direction) {
+        self.moving.retain(|d| d != direction);
+        match self.moving.last().cloned() {
+            Some(direction) => self.face(&direction),
+            None => self.stop(),
+        }
     }
 
+    /// Stops moving into any direction
     pub fn stop(&mut self) {
+        self.moving.clear();
         self.footstep = 0;
+        self.walked = 0.0;
+    }
+
+    /// Returns the directions the player is moving in
+    pub fn moving(&self) -> &[Direction] {
+        &self.moving
+    }
+
+    /// Returns pixels per second the player moves along x and y axis
+    pub fn velocity(&self) -> (f64, f64) {
+        let (x, y) = self.moving.iter().fold((0.0, 0.0), |(x, y), direction| match direction {
+            Direction::Up => (x, y - 1.0),
+            Direction::Down => (x, y + 1.0),
+            Direction::Left => (x - 1.0, y),
+            Direction::Right => (x + 1.0, y),
+        });
+        let length = f64::hypot(x, y);
+        if length == 0.0 {
+            return (0.0, 0.0);
+        }
+        (x / length * SPEED, y / length * SPEED)
+    }
+
+    /// Returns a copy of this player moved by given pixels, doing footsteps while walking
+    pub fn moved_by(&self, dx: f64, dy: f64) -> Player {
+        let mut player = Player {
+            x: self.x + dx,
+            y: self.y + dy,
+            walked: self.walked + f64::hypot(dx, dy),
+            ..self.clone()
+        };
+        if player.footstep == 0 || player.walked >= FOOTSTEP_DISTANCE {
+            player.footstep = player.footstep % 2 + 1;
+            player.walked %= FOOTSTEP_DISTANCE;
+        }
+        player
     }
 
-    fn sprite(&self) -> Sprite {
+    /// Returns the sprite showing the player facing its direction and doing its footstep
+    pub fn sprite(&self) -> Sprite {
         let direction = match self.direction {
             PlayerDirection::Down => sprite::PlayerDirection::Down,
             PlayerDirection::Left => sprite::PlayerDirection::Left,
@@ -0,0 +1,358 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::net::{BoxAreaContent, Command, Direction, Rules};
+
+/// Messages exchanged between server and clients, one per line.
+///
+/// Game commands are converted from and to messages, so there is a single parser for both
+/// clients and server. Lines of unknown commands cannot be parsed.
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Password(String),
+    Login(String, String),
+    Room(String),
+    Join(Option<String>),
+    Reject(String),
+    /// Player id, session token and hash of the level played
+    Welcome(String, String, String),
+    Rules(u8, u32, u32),
+    Spawn(String, i32, i32),
+    Face(String, Direction),
+    Move(String, Direction),
+    Stop(String, i32, i32),
+    Inventory(String, u8, u8, u32),
+    Remove(String),
+    Name(String, String),
+    Chat(String, String),
+    UpdateBoxArea(String, BoxAreaContent),
+    Top(usize),
+    Rank(String),
+    Leaderboard(Vec<(String, u32)>),
+    Ranking(String, usize, u32),
+    ServerShutdown,
+}
+
+impl Message {
+    /// Returns the type of this message.
+    ///
+    /// Used as metrics label, so only a fixed set of types is returned.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Password(_) => "Password",
+            Message::Login(_, _) => "Login",
+            Message::Room(_) => "Room",
+            Message::Join(_) => "Join",
+            Message::Reject(_) => "Reject",
+            Message::Welcome(_, _, _) => "Welcome",
+            Message::Rules(_, _, _) => "Rules",
+            Message::Spawn(_, _, _) => "Spawn",
+            Message::Face(_, _) => "Face",
+            Message::Move(_, _) => "Move",
+            Message::Stop(_, _, _) => "Stop",
+            Message::Inventory(_, _, _, _) => "Inventory",
+            Message::Remove(_) => "Remove",
+            Message::Name(_, _) => "Name",
+            Message::Chat(_, _) => "Chat",
+            Message::UpdateBoxArea(_, _) => "UpdateBoxArea",
+            Message::Top(_) => "Top",
+            Message::Rank(_) => "Rank",
+            Message::Leaderboard(_) => "Leaderboard",
+            Message::Ranking(_, _, _) => "Ranking",
+            Message::ServerShutdown => "ServerShutdown",
+        }
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::Password(password) => write!(f, "Password {}", password),
+            Message::Login(name, password) => write!(f, "Login {} {}", name, password),
+            Message::Room(name) => write!(f, "Room {}", name),
+            Message::Join(None) => write!(f, "Join"),
+            Message::Join(Some(token)) => write!(f, "Join {}", token),
+            Message::Reject(reason) => write!(f, "Reject {}", reason),
+            Message::Welcome(player_id, token, level_hash) => {
+                write!(f, "Welcome {} {} {}", player_id, token, level_hash)
+            }
+            Message::Rules(glass_space, box_refill_after, box_clear_after) => write!(
+                f,
+                "Rules {} {} {}",
+                glass_space, box_refill_after, box_clear_after
+            ),
+            Message::Spawn(player_id, x, y) => write!(f, "Spawn {} {} {}", player_id, x, y),
+            Message::Face(player_id, direction) => write!(f, "Face {} {}", player_id, direction),
+            Message::Move(player_id, direction) => write!(f, "Move {} {}", player_id, direction),
+            Message::Stop(player_id, x, y) => write!(f, "Stop {} {} {}", player_id, x, y),
+            Message::Inventory(player_id, empty_glasses, filled_glasses, points) => write!(
+                f,
+                "Inventory {} {} {} {}",
+                player_id, empty_glasses, filled_glasses, points
+            ),
+            Message::Remove(player_id) => write!(f, "Remove {}", player_id),
+            Message::Name(player_id, name) => write!(f, "Name {} {}", player_id, name),
+            Message::Chat(player_id, text) => write!(f, "Chat {} {}", player_id, text),
+            Message::UpdateBoxArea(id, content) => {
+                write!(f, "UpdateBoxArea {} {}", id, content)
+            }
+            Message::Top(count) => write!(f, "Top {}", count),
+            Message::Rank(name) => write!(f, "Rank {}", name),
+            Message::Leaderboard(scores) => {
+                write!(f, "Leaderboard")?;
+                for (name, points) in scores {
+                    write!(f, " {}:{}", name, points)?;
+                }
+                Ok(())
+            }
+            Message::Ranking(name, rank, points) => {
+                write!(f, "Ranking {} {} {}", name, rank, points)
+            }
+            Message::ServerShutdown => write!(f, "ServerShutdown"),
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(' ').collect::<Vec<_>>();
+
+        match parts.as_slice() {
+            ["Password", password] => Ok(Message::Password(password.to_string())),
+            ["Login", name, password] => Ok(Message::Login(name.to_string(), password.to_string())),
+            ["Room", name] => Ok(Message::Room(name.to_string())),
+            ["Join"] => Ok(Message::Join(None)),
+            ["Join", token] => Ok(Message::Join(Some(token.to_string()))),
+            ["Reject", ..] => Ok(Message::Reject(parts[1..].join(" "))),
+            ["Welcome", player_id, token, level_hash] => Ok(Message::Welcome(
+                player_id.to_string(),
+                token.to_string(),
+                level_hash.to_string(),
+            )),
+            ["Rules", glass_space, box_refill_after, box_clear_after] => Ok(Message::Rules(
+                glass_space.parse().map_err(|_| ())?,
+                box_refill_after.parse().map_err(|_| ())?,
+                box_clear_after.parse().map_err(|_| ())?,
+            )),
+            ["Spawn", player_id, x, y] => Ok(Message::Spawn(
+                player_id.to_string(),
+                x.parse().map_err(|_| ())?,
+                y.parse().map_err(|_| ())?,
+            )),
+            ["Face", player_id, direction] => Ok(Message::Face(
+                player_id.to_string(),
+                direction.parse().map_err(|_| ())?,
+            )),
+            ["Move", player_id, direction] => Ok(Message::Move(
+                player_id.to_string(),
+                direction.parse().map_err(|_| ())?,
+            )),
+            ["Stop", player_id, x, y] => Ok(Message::Stop(
+                player_id.to_string(),
+                x.parse().map_err(|_| ())?,
+                y.parse().map_err(|_| ())?,
+            )),
+            ["Inventory", player_id, empty_glasses, filled_glasses, points] => {
+                Ok(Message::Inventory(
+                    player_id.to_string(),
+                    empty_glasses.parse().map_err(|_| ())?,
+                    filled_glasses.parse().map_err(|_| ())?,
+                    points.parse().map_err(|_| ())?,
+                ))
+            }
+            ["Remove", player_id] => Ok(Message::Remove(player_id.to_string())),
+            ["Name", player_id, name] => Ok(Message::Name(player_id.to_string(), name.to_string())),
+            ["Chat", player_id, ..] => {
+                Ok(Message::Chat(player_id.to_string(), parts[2..].join(" ")))
+            }
+            ["UpdateBoxArea", id, content] => {
+                Ok(Message::UpdateBoxArea(id.to_string(), content.parse()?))
+            }
+            ["Top", count] => Ok(Message::Top(count.parse().map_err(|_| ())?)),
+            ["Rank", name] => Ok(Message::Rank(name.to_string())),
+            ["Leaderboard", scores @ ..] => Ok(Message::Leaderboard(
+                scores
+                    .iter()
+                    .map(|score| match score.split_once(':') {
+                        Some((name, points)) => {
+                            Ok((name.to_string(), points.parse().map_err(|_| ())?))
+                        }
+                        None => Err(()),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            ["Ranking", name, rank, points] => Ok(Message::Ranking(
+                name.to_string(),
+                rank.parse().map_err(|_| ())?,
+                points.parse().map_err(|_| ())?,
+            )),
+            ["ServerShutdown"] => Ok(Message::ServerShutdown),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<&Command> for Message {
+    fn from(command: &Command) -> Self {
+        match command {
+            Command::SpawnPlayer(player_id, x, y) => {
+                Message::Spawn(player_id.clone(), *x as i32, *y as i32)
+            }
+            Command::RemovePlayer(player_id) => Message::Remove(player_id.clone()),
+            Command::FacePlayer(player_id, direction) => {
+                Message::Face(player_id.clone(), direction.clone())
+            }
+            Command::MovePlayer(player_id, direction) => {
+                Message::Move(player_id.clone(), direction.clone())
+            }
+            Command::StopPlayer(player_id, x, y) => {
+                Message::Stop(player_id.clone(), *x as i32, *y as i32)
+            }
+            Command::UpdateInventory(player_id, empty_glasses, filled_glasses, points) => {
+                Message::Inventory(player_id.clone(), *empty_glasses, *filled_glasses, *points)
+            }
+            Command::UpdateLeaderboard(scores) => Message::Leaderboard(scores.clone()),
+            Command::UpdateRanking(name, rank, points) => {
+                Message::Ranking(name.clone(), *rank, *points)
+            }
+            Command::UpdateRules(rules) => Message::Rules(
+                rules.glass_space,
+                rules.box_refill_after as u32,
+                rules.box_clear_after as u32,
+            ),
+            Command::UpdateBoxArea(id, content) => {
+                Message::UpdateBoxArea(id.clone(), content.clone())
+            }
+        }
+    }
+}
+
+impl TryFrom<Message> for Command {
+    type Error = ();
+
+    /// Converts a message into the game command it carries, fails for messages of the handshake
+    /// and the server only
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::Spawn(player_id, x, y) => Ok(Command::SpawnPlayer(
+                player_id,
+                x.try_into().map_err(|_| ())?,
+                y.try_into().map_err(|_| ())?,
+            )),
+            Message::Remove(player_id) => Ok(Command::RemovePlayer(player_id)),
+            Message::Face(player_id, direction) => Ok(Command::FacePlayer(player_id, direction)),
+            Message::Move(player_id, direction) => Ok(Command::MovePlayer(player_id, direction)),
+            Message::Stop(player_id, x, y) => Ok(Command::StopPlayer(
+                player_id,
+                x.try_into().map_err(|_| ())?,
+                y.try_into().map_err(|_| ())?,
+            )),
+            Message::Inventory(player_id, empty_glasses, filled_glasses, points) => Ok(
+                Command::UpdateInventory(player_id, empty_glasses, filled_glasses, points),
+            ),
+            Message::Leaderboard(scores) => Ok(Command::UpdateLeaderboard(scores)),
+            Message::Ranking(name, rank, points) => Ok(Command::UpdateRanking(name, rank, points)),
+            Message::Rules(glass_space, box_refill_after, box_clear_after) => {
+                Ok(Command::UpdateRules(Rules {
+                    glass_space,
+                    box_refill_after: box_refill_after.into(),
+                    box_clear_after: box_clear_after.into(),
+                }))
+            }
+            Message::UpdateBoxArea(id, content) => Ok(Command::UpdateBoxArea(id, content)),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::{BoxAreaContent, Direction};
+    use crate::protocol::Message;
+
+    #[test]
+    fn should_deserialize_message_line() {
+        assert_eq!(Message::Join(None), "Join".parse::<Message>().unwrap());
+        assert_eq!(
+            Message::Join(Some("0123456789abcdef".to_string())),
+            "Join 0123456789abcdef".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Login("alice".to_string(), "secret".to_string()),
+            "Login alice secret".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Reject("Wrong server password".to_string()),
+            "Reject Wrong server password".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Chat("1234".to_string(), "Cheers, everyone!".to_string()),
+            "Chat 1234 Cheers, everyone!".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Welcome(
+                "1234".to_string(),
+                "0123456789abcdef".to_string(),
+                "5eed".to_string()
+            ),
+            "Welcome 1234 0123456789abcdef 5eed"
+                .parse::<Message>()
+                .unwrap()
+        );
+        assert_eq!(
+            Message::Room("cellar".to_string()),
+            "Room cellar".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Rules(5, 10, 30),
+            "Rules 5 10 30".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Move("1234".to_string(), Direction::Left),
+            "Move 1234 Left".parse::<Message>().unwrap()
+        );
+        assert!("Move 1234 Sideways".parse::<Message>().is_err());
+        assert_eq!(
+            Message::Stop("1234".to_string(), 100, 200),
+            "Stop 1234 100 200".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Inventory("1234".to_string(), 1, 2, 30),
+            "Inventory 1234 1 2 30".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::UpdateBoxArea("RightBottom".to_string(), BoxAreaContent::HiddenBox),
+            "UpdateBoxArea RightBottom HiddenBox"
+                .parse::<Message>()
+                .unwrap()
+        );
+        assert_eq!(Message::Top(5), "Top 5".parse::<Message>().unwrap());
+        assert_eq!(
+            Message::Rank("alice".to_string()),
+            "Rank alice".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Leaderboard(vec![("alice".to_string(), 50), ("bob".to_string(), 30)]),
+            "Leaderboard alice:50 bob:30".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Ranking("alice".to_string(), 1, 50),
+            "Ranking alice 1 50".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::ServerShutdown,
+            "ServerShutdown".parse::<Message>().unwrap()
+        );
+        assert_eq!(
+            Message::Face("1234".to_string(), Direction::Left),
+            "Face 1234 Left".parse::<Message>().unwrap()
+        );
+        assert!("UpdateBoxArea RightBottom Treasure"
+            .parse::<Message>()
+            .is_err());
+        assert!("Wave 1234".parse::<Message>().is_err());
+        assert!("".parse::<Message>().is_err());
+    }
+}
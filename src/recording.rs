@@ -0,0 +1,236 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use log::error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::time::Instant;
+
+use crate::transport::{Stream, BUFFER_SIZE};
+
+/// A line sent or received by the recording side of a connection
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// Milliseconds since the recording started
+    pub millis: u64,
+    pub connection: u64,
+    pub received: bool,
+    pub line: String,
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let direction = if self.received { "<" } else { ">" };
+        write!(
+            f,
+            "{} {} {} {}",
+            self.millis, self.connection, direction, self.line
+        )
+    }
+}
+
+impl FromStr for Entry {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.splitn(4, ' ').collect::<Vec<_>>().as_slice() {
+            [millis, connection, direction, line] => Ok(Entry {
+                millis: millis.parse().map_err(|_| ())?,
+                connection: connection.parse().map_err(|_| ())?,
+                received: match *direction {
+                    "<" => true,
+                    ">" => false,
+                    _ => return Err(()),
+                },
+                line: line.to_string(),
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Writes every line exchanged on recorded connections to a file, with timestamps and connection ids.
+///
+/// Passwords are not recorded. Entries are written by a background thread, so recording
+/// connections never wait for the file.
+#[derive(Clone)]
+pub struct Recorder {
+    entries: Sender<Entry>,
+    started_at: Instant,
+}
+
+impl Recorder {
+    /// Creates the recording file, replacing an existing one
+    pub fn create(file: &str) -> Result<Recorder, String> {
+        let file = File::create(file).map_err(|e| format!("Cannot create {}: {}", file, e))?;
+        let (entries, receiver) = mpsc::channel();
+        std::thread::spawn(move || write(BufWriter::new(file), receiver));
+        Ok(Recorder {
+            entries,
+            started_at: Instant::now(),
+        })
+    }
+
+    fn record(&self, connection: u64, received: bool, line: &str) {
+        let line = match line.split(' ').collect::<Vec<_>>().as_slice() {
+            ["Password", _] => "Password ***".to_string(),
+            ["Login", name, _] => format!("Login {} ***", name),
+            _ => line.to_string(),
+        };
+        let entry = Entry {
+            millis: self.started_at.elapsed().as_millis() as u64,
+            connection,
+            received,
+            line,
+        };
+        let _r = self.entries.send(entry);
+    }
+
+    /// Wraps a stream to record all lines exchanged using the given connection id.
+    ///
+    /// Returns the stream to be used instead, lines are forwarded by background tasks
+    /// of the current runtime.
+    pub fn wrap(&self, stream: impl Stream, connection: u64) -> DuplexStream {
+        let (inner, outer) = tokio::io::duplex(BUFFER_SIZE);
+        let (stream_reader, stream_writer) = tokio::io::split(stream);
+        let (inner_reader, inner_writer) = tokio::io::split(inner);
+        tokio::spawn(forward(
+            stream_reader,
+            inner_writer,
+            self.clone(),
+            connection,
+            true,
+        ));
+        tokio::spawn(forward(
+            inner_reader,
+            stream_writer,
+            self.clone(),
+            connection,
+            false,
+        ));
+        outer
+    }
+}
+
+/// Writes entries until all recorders are dropped, flushing whenever no more entries are queued
+fn write(mut writer: BufWriter<File>, entries: Receiver<Entry>) {
+    while let Ok(entry) = entries.recv() {
+        let written = std::iter::once(entry)
+            .chain(entries.try_iter())
+            .try_for_each(|entry| writeln!(writer, "{}", entry))
+            .and_then(|_| writer.flush());
+        if let Err(e) = written {
+            error!("Cannot record lines: {}", e);
+        }
+    }
+}
+
+/// Forwards and records lines until the reader is closed
+async fn forward<R, W>(
+    reader: R,
+    mut writer: W,
+    recorder: Recorder,
+    connection: u64,
+    received: bool,
+) where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        recorder.record(connection, received, &line);
+        if writer
+            .write_all(format!("{}\n", line).as_bytes())
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+    let _r = writer.shutdown().await;
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    use crate::recording::{Entry, Recorder};
+    use crate::transport::loopback;
+
+    #[test]
+    fn should_parse_entries() {
+        assert_eq!(
+            Ok(Entry {
+                millis: 1500,
+                connection: 3,
+                received: true,
+                line: "Chat 1234 Hello there".to_string(),
+            }),
+            "1500 3 < Chat 1234 Hello there".parse::<Entry>()
+        );
+        assert_eq!(
+            "20 1 > Welcome 1234 0123456789abcdef",
+            Entry {
+                millis: 20,
+                connection: 1,
+                received: false,
+                line: "Welcome 1234 0123456789abcdef".to_string(),
+            }
+            .to_string()
+        );
+        assert!("20 1 ? Join".parse::<Entry>().is_err());
+    }
+
+    #[tokio::test]
+    async fn should_record_lines_without_passwords() {
+        let file = std::env::temp_dir().join(format!("winelounge-{}.rec", rand::random::<u32>()));
+        let recorder = Recorder::create(file.to_str().unwrap()).unwrap();
+        let (mut listener, connector) = loopback::listen();
+        let mut client = connector.connect().unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let mut server = BufReader::new(recorder.wrap(server, 7)).lines();
+
+        client
+            .write_all(b"Password secret\nLogin alice secret\nJoin\n")
+            .await
+            .unwrap();
+        while server.next_line().await.unwrap() != Some("Join".to_string()) {}
+        server
+            .get_mut()
+            .write_all(b"Welcome 1234 0123456789abcdef\n")
+            .await
+            .unwrap();
+        let mut client = BufReader::new(client).lines();
+        client.next_line().await.unwrap();
+
+        // Lines are written in the background
+        let mut entries = vec![];
+        for _ in 0..100 {
+            entries = std::fs::read_to_string(&file)
+                .unwrap()
+                .lines()
+                .map(|line| line.parse::<Entry>().unwrap())
+                .collect::<Vec<_>>();
+            if entries.len() == 4 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let _r = std::fs::remove_file(file);
+        assert_eq!(
+            vec![
+                (true, "Password ***"),
+                (true, "Login alice ***"),
+                (true, "Join"),
+                (false, "Welcome 1234 0123456789abcdef"),
+            ],
+            entries
+                .iter()
+                .map(|entry| (entry.received, entry.line.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert!(entries.iter().all(|entry| entry.connection == 7));
+    }
+}
@@ -0,0 +1,479 @@
+use crate::camera::{Camera, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::editor::{Editor, Tool, GRID, SPAWN};
+use crate::geometry::Rect;
+use crate::net::{BoxAreaContent, Direction};
+use crate::player::Player;
+use crate::sprite::Sprite;
+use crate::world::{BoxArea, World};
+
+/// Color with alpha channel, fully opaque colors have an alpha of 255
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+}
+
+/// Color of the ground the world is drawn on
+const GROUND: Color = Color::rgb(206, 182, 115);
+
+/// Color of the HUD bar on top of the world
+const HUD: Color = Color::rgb(160, 90, 44);
+
+/// Color of the glass shelf within the HUD bar
+const SHELF: Color = Color::rgb(128, 51, 0);
+
+/// Color of the grid lines shown while editing
+const GRID_LINE: Color = Color::rgba(128, 51, 0, 40);
+
+/// Color of the translucent box the leaderboard is shown in
+const OVERLAY: Color = Color::rgba(44, 48, 63, 220);
+
+/// Target of drawing operations, e.g. a window or a list of operations for tests
+pub trait Renderer {
+    /// Clears the whole target using given color
+    fn clear(&mut self, color: Color);
+
+    /// Fills given rect, blending translucent colors with what is drawn already
+    fn fill_rect(&mut self, rect: Rect, color: Color);
+
+    /// Draws sprite with its left top corner at given position
+    fn draw_sprite(&mut self, sprite: &Sprite, x: i32, y: i32);
+
+    /// Draws text with its left top corner at given position and returns its width
+    fn draw_text(&mut self, text: &str, x: i32, y: i32) -> i32;
+
+    /// Returns the width of text if drawn
+    fn text_width(&self, text: &str) -> i32;
+
+    /// Shows everything drawn since the last call
+    fn present(&mut self);
+}
+
+/// Renders world using given renderer.
+///
+/// The world is seen through a camera following the local player, the HUD bar stays on top of the screen.
+/// The leaderboard is shown on top of the world if requested.
+pub fn render_world(renderer: &mut impl Renderer, world: &World, show_leaderboard: bool) {
+    renderer.clear(GROUND);
+
+    let player = world.player();
+    let camera = Camera::following(player.center(), world.level());
+    let mut view = camera.view(renderer);
+
+    // Lounges
+    for lounge in world.lounge_rects() {
+        view.draw_sprite(&Sprite::Lounge, lounge.x, lounge.y);
+    }
+
+    // Box Areas
+    for box_area in world.box_areas() {
+        render_box_area(&mut view, box_area);
+    }
+
+    // Decoration
+    for f in world.flowers() {
+        view.draw_sprite(&Sprite::Flower, f.x, f.y)
+    }
+
+    // Stops
+    for s in world.stops() {
+        view.draw_sprite(&Sprite::Stone, s.x, s.y)
+    }
+
+    // Players
+    for player in world.remote_players() {
+        render_player(&mut view, player);
+    }
+    render_player(&mut view, player);
+
+    renderer.fill_rect(Rect::new(0, 0, SCREEN_WIDTH, 45), HUD);
+
+    // Points/Glasses
+    let glass_space = world.rules().glass_space;
+    (1..=glass_space).for_each(|i| {
+        renderer.fill_rect(Rect::new(5, 37, glass_space as u32 * 25 + 5, 4), SHELF);
+
+        if player.filled_glasses + player.empty_glasses >= i {
+            renderer.draw_sprite(&Sprite::GlassEmpty, (i as i32) * 25 - 15, 10);
+        }
+        if player.filled_glasses >= i {
+            renderer.draw_sprite(&Sprite::GlassFilled, (i as i32) * 25 - 15, 10);
+        }
+    });
+
+    // Points
+    let mut x = SCREEN_WIDTH as i32 - 10;
+    x -= draw_text_right(renderer, &format!("Score: {:#04}", player.points), x, 16);
+    if let Some((rank, points)) = world.ranking() {
+        draw_text_right(
+            renderer,
+            &format!("Best: #{} ({})", rank, points),
+            x - 20,
+            16,
+        );
+    }
+
+    // Leaderboard
+    if show_leaderboard {
+        let leaderboard = world.leaderboard();
+        let height = 50 + 22 * leaderboard.len() as u32;
+        renderer.fill_rect(Rect::new(250, 100, 300, height), OVERLAY);
+
+        renderer.draw_text("Leaderboard", 260, 110);
+        for (i, (name, points)) in leaderboard.iter().enumerate() {
+            let y = 140 + 22 * i as i32;
+            renderer.draw_text(&format!("{}. {}", i + 1, name), 260, y);
+            draw_text_right(renderer, &points.to_string(), 540, y);
+        }
+    }
+
+    renderer.present();
+}
+
+/// Renders the level of an editor with spawn points, the grid if snapping to it
+/// and the tools in the HUD bar, the selected one highlighted.
+pub fn render_editor(renderer: &mut impl Renderer, editor: &Editor) {
+    let level = editor.level();
+    renderer.clear(GROUND);
+
+    let camera = editor.camera();
+    let mut view = camera.view(renderer);
+
+    // Grid lines within the screen
+    if editor.snap() {
+        let right = level.width.min(camera.x as u32 + SCREEN_WIDTH) as i32;
+        let bottom = level.height.min(camera.y as u32 + SCREEN_HEIGHT) as i32;
+        let first_x = (camera.x + GRID - 1) / GRID * GRID;
+        let first_y = (camera.y + GRID - 1) / GRID * GRID;
+        for x in (first_x..right).step_by(GRID as usize) {
+            let height = (bottom - camera.y) as u32;
+            view.fill_rect(Rect::new(x, camera.y, 1, height), GRID_LINE);
+        }
+        for y in (first_y..bottom).step_by(GRID as usize) {
+            let width = (right - camera.x) as u32;
+            view.fill_rect(Rect::new(camera.x, y, width, 1), GRID_LINE);
+        }
+    }
+
+    for lounge in &level.lounges {
+        view.draw_sprite(&Sprite::Lounge, lounge.x, lounge.y);
+    }
+    for box_area in &level.box_areas {
+        render_box_area(&mut view, &box_area.box_area());
+    }
+    for flower in &level.flowers {
+        view.draw_sprite(&Sprite::Flower, flower.x, flower.y);
+    }
+    for stone in &level.stones {
+        view.draw_sprite(&Sprite::Stone, stone.x, stone.y);
+    }
+    for spawn in &level.spawns {
+        view.draw_sprite(&SPAWN, spawn.x, spawn.y);
+    }
+
+    // Tools
+    renderer.fill_rect(Rect::new(0, 0, SCREEN_WIDTH, 45), HUD);
+    let mut x = 10;
+    for (i, tool) in Tool::ALL.iter().enumerate() {
+        let text = format!("{} {}", i + 1, tool.name());
+        let width = renderer.text_width(&text);
+        if *tool == editor.tool() {
+            renderer.fill_rect(Rect::new(x - 5, 8, width as u32 + 10, 29), SHELF);
+        }
+        x += renderer.draw_text(&text, x, 16) + 20;
+    }
+    draw_text_right(renderer, editor.status(), SCREEN_WIDTH as i32 - 10, 16);
+
+    renderer.present();
+}
+
+/// Renders player
+fn render_player(renderer: &mut impl Renderer, player: &Player) {
+    let position = player.position();
+    renderer.draw_sprite(&player.sprite(), position.x, position.y);
+}
+
+/// Renders BoxArea with bushes on each side but its opening and its content in the middle
+fn render_box_area(renderer: &mut impl Renderer, box_area: &BoxArea) {
+    let rect = box_area.bounding_rect();
+    let (width, height) = (rect.width as i32, rect.height as i32);
+
+    // Border
+    for (sprite, x, y) in box_area_bushes(rect, box_area.opening()) {
+        renderer.draw_sprite(&sprite, x, y);
+    }
+
+    // Box
+    let box_sprite = match &box_area.content {
+        BoxAreaContent::HiddenBox => Sprite::HiddenBox,
+        BoxAreaContent::FilledBottle => Sprite::BottleFilled,
+        BoxAreaContent::EmptyBottle => Sprite::BottleEmpty,
+        BoxAreaContent::EmptyGlass => Sprite::GlassEmpty,
+        BoxAreaContent::Nothing => Sprite::Nothing,
+    };
+    let (box_width, box_height) = box_sprite.size();
+    renderer.draw_sprite(
+        &box_sprite,
+        rect.x + (width - box_width as i32) / 2,
+        rect.y + (height - box_height as i32) / 2,
+    );
+}
+
+/// Returns the bushes surrounding a box area on all sides but its opening, with their position
+pub fn box_area_bushes(rect: Rect, opening: &Direction) -> Vec<(Sprite, i32, i32)> {
+    let center = rect.center();
+    let (long, short) = Sprite::BushHorizontal.size();
+    let (long, short) = (long as i32, short as i32);
+    let top = (center.x - long / 2, rect.y);
+    let bottom = (center.x - long / 2, rect.bottom() - short);
+    let left = (rect.x, center.y - long / 2);
+    let right = (rect.right() - short, center.y - long / 2);
+    let (horizontal, vertical) = match opening {
+        Direction::Left => (vec![top, bottom], vec![right]),
+        Direction::Right => (vec![top, bottom], vec![left]),
+        Direction::Up => (vec![bottom], vec![left, right]),
+        Direction::Down => (vec![top], vec![left, right]),
+    };
+    let horizontal = horizontal
+        .into_iter()
+        .map(|(x, y)| (Sprite::BushHorizontal, x, y));
+    let vertical = vertical
+        .into_iter()
+        .map(|(x, y)| (Sprite::BushVertical, x, y));
+    horizontal.chain(vertical).collect()
+}
+
+/// Draws text with its right side at given position and returns its width
+fn draw_text_right(renderer: &mut impl Renderer, text: &str, x: i32, y: i32) -> i32 {
+    let width = renderer.text_width(text);
+    renderer.draw_text(text, x - width, y)
+}
+
+/// Drawing operation kept by the recording renderer
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    Clear(Color),
+    FillRect(Rect, Color),
+    DrawSprite(Sprite, i32, i32),
+    DrawText(String, i32, i32),
+    Present,
+}
+
+/// Renderer keeping operations instead of drawing them, e.g. to check what a frame consists of.
+///
+/// Clearing starts a new frame and drops the operations of the previous one.
+/// Text is measured with a fixed width per character.
+#[derive(Debug, Default)]
+pub struct RecordingRenderer {
+    pub operations: Vec<Operation>,
+}
+
+impl RecordingRenderer {
+    /// Width of each character of drawn text
+    pub const CHAR_WIDTH: i32 = 8;
+
+    /// Returns all texts drawn
+    pub fn texts(&self) -> Vec<&str> {
+        self.operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::DrawText(text, _, _) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Renderer for RecordingRenderer {
+    fn clear(&mut self, color: Color) {
+        self.operations.clear();
+        self.operations.push(Operation::Clear(color));
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: Color) {
+        self.operations.push(Operation::FillRect(rect, color));
+    }
+
+    fn draw_sprite(&mut self, sprite: &Sprite, x: i32, y: i32) {
+        self.operations
+            .push(Operation::DrawSprite(sprite.clone(), x, y));
+    }
+
+    fn draw_text(&mut self, text: &str, x: i32, y: i32) -> i32 {
+        self.operations
+            .push(Operation::DrawText(text.to_string(), x, y));
+        self.text_width(text)
+    }
+
+    fn text_width(&self, text: &str) -> i32 {
+        text.chars().count() as i32 * Self::CHAR_WIDTH
+    }
+
+    fn present(&mut self) {
+        self.operations.push(Operation::Present);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::editor::{Editor, Tool};
+    use crate::geometry::{Point, Rect};
+    use crate::level::Level;
+    use crate::net::{BoxAreaContent, Command, Direction};
+    use crate::render::{render_editor, render_world, Color, Operation, RecordingRenderer};
+    use crate::sprite::{PlayerDirection, PlayerFootstep, Sprite};
+    use crate::world::{BoxArea, World};
+
+    #[test]
+    fn should_render_players_and_hud() {
+        let mut world = World::init();
+        world.set_player_id("1234");
+        world.execute_command(Command::SpawnPlayer("1234".to_string(), 380, 250));
+        world.execute_command(Command::SpawnPlayer("5678".to_string(), 100, 300));
+        world.execute_command(Command::UpdateInventory("1234".to_string(), 1, 1, 7));
+        let mut renderer = RecordingRenderer::default();
+
+        render_world(&mut renderer, &world, false);
+
+        let player = Operation::DrawSprite(
+            Sprite::Player(PlayerDirection::Down, PlayerFootstep::None),
+            380,
+            250,
+        );
+        let remote_player = Operation::DrawSprite(
+            Sprite::Player(PlayerDirection::Down, PlayerFootstep::None),
+            100,
+            300,
+        );
+        let operations = &renderer.operations;
+        assert!(operations.contains(&remote_player));
+        // The local player is drawn on top of everything else in the world, below the HUD bar
+        assert_eq!(
+            Some(&Operation::FillRect(
+                Rect::new(0, 0, 800, 45),
+                Color::rgb(160, 90, 44)
+            )),
+            operations.iter().skip_while(|o| **o != player).nth(1)
+        );
+        assert!(operations.contains(&Operation::DrawText("Score: 0007".to_string(), 702, 16)));
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::GlassEmpty, 10, 10)));
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::GlassEmpty, 35, 10)));
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::GlassFilled, 10, 10)));
+        assert!(!operations.contains(&Operation::DrawSprite(Sprite::GlassFilled, 35, 10)));
+        assert_eq!(Some(&Operation::Present), operations.last());
+        assert_eq!(vec!["Score: 0007"], renderer.texts());
+    }
+
+    #[test]
+    fn should_follow_local_player_within_larger_level() {
+        let mut world = World::init();
+        world.set_level(&Level {
+            width: 2000,
+            height: 1500,
+            ..Level::default()
+        });
+        world.set_player_id("1234");
+        world.execute_command(Command::SpawnPlayer("1234".to_string(), 1000, 800));
+        world.execute_command(Command::SpawnPlayer("5678".to_string(), 1100, 900));
+        let mut renderer = RecordingRenderer::default();
+
+        render_world(&mut renderer, &world, false);
+
+        let sprite = Sprite::Player(PlayerDirection::Down, PlayerFootstep::None);
+        let operations = &renderer.operations;
+        assert!(operations.contains(&Operation::DrawSprite(sprite.clone(), 380, 243)));
+        assert!(operations.contains(&Operation::DrawSprite(sprite, 480, 343)));
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::Stone, -30, -107)));
+        assert!(operations.contains(&Operation::DrawText("Score: 0000".to_string(), 702, 16)));
+    }
+
+    #[test]
+    fn should_render_bushes_around_box_area_but_its_opening() {
+        let mut world = World::init();
+        world.add_box_area(BoxArea::new(
+            "cellar",
+            Rect::new(400, 400, 60, 60),
+            Direction::Up,
+            BoxAreaContent::EmptyGlass,
+        ));
+        let mut renderer = RecordingRenderer::default();
+
+        render_world(&mut renderer, &world, false);
+
+        let operations = &renderer.operations;
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::BushHorizontal, 405, 435)));
+        assert!(!operations.contains(&Operation::DrawSprite(Sprite::BushHorizontal, 405, 400)));
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::BushVertical, 400, 405)));
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::BushVertical, 435, 405)));
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::GlassEmpty, 420, 417)));
+    }
+
+    #[test]
+    fn should_render_leaderboard_if_requested() {
+        let mut world = World::init();
+        world.execute_command(Command::UpdateLeaderboard(vec![
+            ("alice".to_string(), 50),
+            ("bob".to_string(), 30),
+        ]));
+        let mut renderer = RecordingRenderer::default();
+
+        render_world(&mut renderer, &world, true);
+
+        assert_eq!(
+            vec![
+                "Score: 0000",
+                "Leaderboard",
+                "1. alice",
+                "50",
+                "2. bob",
+                "30"
+            ],
+            renderer.texts()
+        );
+    }
+
+    #[test]
+    fn should_render_level_and_tools_of_editor() {
+        let mut editor = Editor::new(Level::default(), "level.json");
+        editor.set_tool(Tool::Flower);
+        editor.press(Point::new(100, 300));
+        let mut renderer = RecordingRenderer::default();
+
+        render_editor(&mut renderer, &editor);
+
+        let operations = &renderer.operations;
+        assert!(operations.contains(&Operation::DrawSprite(Sprite::Flower, 100, 300)));
+        assert!(operations.contains(&Operation::DrawSprite(
+            Sprite::Player(PlayerDirection::Down, PlayerFootstep::None),
+            380,
+            250
+        )));
+        assert_eq!(
+            vec![
+                "1 Stone",
+                "2 Flower",
+                "3 Box area",
+                "4 Lounge",
+                "5 Spawn",
+                "Editing level.json"
+            ],
+            renderer.texts()
+        );
+        assert!(operations.contains(&Operation::FillRect(
+            Rect::new(81, 8, 74, 29),
+            Color::rgb(128, 51, 0)
+        )));
+    }
+}
@@ -0,0 +1,73 @@
+use sdl2::pixels;
+use sdl2::render::{BlendMode, Texture, WindowCanvas};
+use sdl2::ttf::Font;
+
+use winelounge::geometry::Rect;
+use winelounge::render::{Color, Renderer};
+use winelounge::sprite::Sprite;
+
+/// Renderer drawing onto an SDL window, using the sprite sheet and font loaded from the assets
+pub struct SdlRenderer<'a> {
+    pub canvas: WindowCanvas,
+    pub texture: Texture<'a>,
+    pub font: Font<'a, 'static>,
+}
+
+impl Renderer for SdlRenderer<'_> {
+    fn clear(&mut self, color: Color) {
+        self.canvas.set_draw_color(sdl_color(color));
+        self.canvas.clear();
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: Color) {
+        if color.a < 255 {
+            self.canvas.set_blend_mode(BlendMode::Blend);
+        }
+        self.canvas.set_draw_color(sdl_color(color));
+        let _r = self.canvas.fill_rect(sdl_rect(rect));
+        self.canvas.set_blend_mode(BlendMode::None);
+    }
+
+    fn draw_sprite(&mut self, sprite: &Sprite, x: i32, y: i32) {
+        let rect = sprite.rect();
+        let render_rect = Rect::new(x, y, rect.width, rect.height);
+        let _r = self
+            .canvas
+            .copy(&self.texture, sdl_rect(rect), sdl_rect(render_rect));
+    }
+
+    fn draw_text(&mut self, text: &str, x: i32, y: i32) -> i32 {
+        let surface = self
+            .font
+            .render(text)
+            .blended(pixels::Color::RGBA(246, 222, 155, 255))
+            .unwrap();
+        let texture_creator = self.canvas.texture_creator();
+        let texture = texture_creator
+            .create_texture_from_surface(&surface)
+            .unwrap();
+
+        let _r = self.canvas.copy(
+            &texture,
+            surface.rect(),
+            Some(sdl_rect(Rect::new(x, y, surface.width(), surface.height()))),
+        );
+        surface.width() as i32
+    }
+
+    fn text_width(&self, text: &str) -> i32 {
+        self.font.size_of(text).map_or(0, |(width, _)| width as i32)
+    }
+
+    fn present(&mut self) {
+        self.canvas.present();
+    }
+}
+
+fn sdl_color(color: Color) -> pixels::Color {
+    pixels::Color::RGBA(color.r, color.g, color.b, color.a)
+}
+
+fn sdl_rect(rect: Rect) -> sdl2::rect::Rect {
+    sdl2::rect::Rect::new(rect.x, rect.y, rect.width, rect.height)
+}
@@ -1,6 +1,7 @@
-use sdl2::rect::Rect;
-use sdl2::render::{Texture, WindowCanvas};
+use crate::geometry::Rect;
 
+/// Sprites of the sprite sheet, each one with its bounding rect within the sheet
+#[derive(Clone, Debug, PartialEq)]
 pub enum Sprite {
     BottleEmpty,
     BottleFilled,
@@ -16,12 +17,14 @@ pub enum Sprite {
     Stone,
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum PlayerFootstep {
     None = 5,
     Left = 60,
     Right = 115,
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum PlayerDirection {
     Down,
     Left,
@@ -53,12 +56,6 @@ impl Sprite {
         self.rect().size()
     }
 
-    /// Renders current sprite with given Canvas and texture at the given position,
-    pub fn render(&self, canvas: &mut WindowCanvas, texture: &Texture, x: i32, y: i32) {
-        let render_rect = Rect::new(x, y, self.rect().width(), self.rect().height());
-        let _r = canvas.copy(texture, self.rect(), render_rect);
-    }
-
     fn player_rect(player_direction: &PlayerDirection, footstep: &PlayerFootstep) -> Rect {
         let x = match footstep {
             PlayerFootstep::Left => 60,
@@ -0,0 +1,293 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::time::{sleep_until, Instant};
+
+/// Bytes buffered in each direction of in-memory streams before writes wait for the other side to read
+pub const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Byte stream carrying the line-based protocol between client and server,
+/// e.g. a TCP connection, a TLS connection or an in-memory loopback connection.
+pub trait Stream: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<S> Stream for S where S: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+/// Conditions of a bad network, applied to each line sent in either direction.
+///
+/// Parsed from a comma separated list like `latency=100ms,jitter=30ms,loss=5%,duplicate=1%,reorder=2%`,
+/// missing values default to a perfect network.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    pub latency: Duration,
+    /// Maximum random deviation from latency, in both directions
+    pub jitter: Duration,
+    /// Probability of a line to get lost
+    pub loss: f64,
+    /// Probability of a line to be delivered twice
+    pub duplication: f64,
+    /// Probability of a line to be overtaken by following lines
+    pub reordering: f64,
+}
+
+impl NetworkConditions {
+    /// Returns how often a line is delivered: never if lost, twice if duplicated
+    fn copies(&self) -> usize {
+        if rand::random::<f64>() < self.loss {
+            0
+        } else if rand::random::<f64>() < self.duplication {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Returns when a line sent now is delivered.
+    ///
+    /// Unless reordered, lines are delivered in the order sent, after the previous line.
+    fn delivery_time(&self, now: Instant, previous: &mut Instant) -> Instant {
+        let jitter = self.jitter.mul_f64(rand::random::<f64>() * 2.0);
+        let delivery_time = (now + self.latency + jitter)
+            .checked_sub(self.jitter)
+            .unwrap_or(now)
+            .max(now);
+        if rand::random::<f64>() < self.reordering {
+            return delivery_time + self.latency + self.jitter * 2 + Duration::from_millis(10);
+        }
+        *previous = delivery_time.max(*previous);
+        *previous
+    }
+}
+
+impl FromStr for NetworkConditions {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut conditions = NetworkConditions::default();
+
+        for condition in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            match condition.split_once('=').ok_or(())? {
+                ("latency", value) => conditions.latency = parse_millis(value)?,
+                ("jitter", value) => conditions.jitter = parse_millis(value)?,
+                ("loss", value) => conditions.loss = parse_percent(value)?,
+                ("duplicate", value) => conditions.duplication = parse_percent(value)?,
+                ("reorder", value) => conditions.reordering = parse_percent(value)?,
+                _ => return Err(()),
+            }
+        }
+
+        Ok(conditions)
+    }
+}
+
+fn parse_millis(value: &str) -> Result<Duration, ()> {
+    let millis = value.strip_suffix("ms").ok_or(())?;
+    Ok(Duration::from_millis(millis.parse().map_err(|_| ())?))
+}
+
+fn parse_percent(value: &str) -> Result<f64, ()> {
+    let percent = value
+        .strip_suffix('%')
+        .ok_or(())?
+        .parse::<f64>()
+        .map_err(|_| ())?;
+    if (0.0..=100.0).contains(&percent) {
+        Ok(percent / 100.0)
+    } else {
+        Err(())
+    }
+}
+
+/// Wraps a stream to deliver lines in both directions under given network conditions.
+///
+/// Returns the stream to be used instead, lines are forwarded by background tasks
+/// of the current runtime.
+pub fn simulate(stream: impl Stream, conditions: NetworkConditions) -> DuplexStream {
+    let (inner, outer) = tokio::io::duplex(BUFFER_SIZE);
+    let (stream_reader, stream_writer) = tokio::io::split(stream);
+    let (inner_reader, inner_writer) = tokio::io::split(inner);
+    tokio::spawn(forward(stream_reader, inner_writer, conditions.clone()));
+    tokio::spawn(forward(inner_reader, stream_writer, conditions));
+    outer
+}
+
+/// Forwards lines, each one at its delivery time, until the reader is closed and all lines are delivered
+async fn forward<R, W>(reader: R, mut writer: W, conditions: NetworkConditions)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+    let mut pending = BinaryHeap::new();
+    let mut sequence = 0_u64;
+    let mut previous = Instant::now();
+    let mut open = true;
+
+    while open || !pending.is_empty() {
+        let next = pending.peek().map(|Reverse((at, _, _))| *at);
+        tokio::select! {
+            line = lines.next_line(), if open => match line {
+                Ok(Some(line)) => {
+                    for _ in 0..conditions.copies() {
+                        let at = conditions.delivery_time(Instant::now(), &mut previous);
+                        sequence += 1;
+                        pending.push(Reverse((at, sequence, line.clone())));
+                    }
+                }
+                _ => open = false,
+            },
+            _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                if let Some(Reverse((_, _, line))) = pending.pop() {
+                    if writer.write_all(format!("{}\n", line).as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+    let _r = writer.shutdown().await;
+}
+
+/// In-memory transport to run a server and its clients within one process, without sockets
+pub mod loopback {
+    use std::io::{Error, ErrorKind};
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use tokio::io::DuplexStream;
+    use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+    use crate::transport::BUFFER_SIZE;
+
+    /// Creates an in-memory listener and a connector to open connections to it
+    pub fn listen() -> (LoopbackListener, LoopbackConnector) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (
+            LoopbackListener {
+                receiver,
+                next_port: 1024,
+            },
+            LoopbackConnector { sender },
+        )
+    }
+
+    /// Accepting side of the loopback transport
+    pub struct LoopbackListener {
+        receiver: UnboundedReceiver<DuplexStream>,
+        next_port: u16,
+    }
+
+    impl LoopbackListener {
+        /// Waits for the next connection.
+        ///
+        /// Each connection gets its own port on 127.0.0.1 as its peer address.
+        pub async fn accept(&mut self) -> std::io::Result<(DuplexStream, SocketAddr)> {
+            let stream = self.receiver.recv().await.ok_or(Error::new(
+                ErrorKind::ConnectionAborted,
+                "All loopback connectors closed",
+            ))?;
+            let address = SocketAddr::from((Ipv4Addr::LOCALHOST, self.next_port));
+            self.next_port = self.next_port.wrapping_add(1);
+            Ok((stream, address))
+        }
+    }
+
+    /// Connecting side of the loopback transport
+    #[derive(Clone)]
+    pub struct LoopbackConnector {
+        sender: UnboundedSender<DuplexStream>,
+    }
+
+    impl LoopbackConnector {
+        /// Opens a connection to the listener, does not wait for it to be accepted
+        pub fn connect(&self) -> std::io::Result<DuplexStream> {
+            let (client, server) = tokio::io::duplex(BUFFER_SIZE);
+            self.sender.send(server).map_err(|_| {
+                Error::new(ErrorKind::ConnectionRefused, "Loopback listener closed")
+            })?;
+            Ok(client)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::time::Instant;
+
+    use crate::transport::{loopback, simulate, NetworkConditions};
+
+    /// Sends lines from a client using given network conditions and returns the lines received by the listener
+    async fn transfer(lines: &str, conditions: NetworkConditions) -> Vec<String> {
+        let (mut listener, connector) = loopback::listen();
+        let mut client = simulate(connector.connect().unwrap(), conditions);
+        let (server, _) = listener.accept().await.unwrap();
+
+        client.write_all(lines.as_bytes()).await.unwrap();
+        client.shutdown().await.unwrap();
+        drop(client);
+
+        let mut received = vec![];
+        let mut lines = BufReader::new(server).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            received.push(line);
+        }
+        received
+    }
+
+    #[test]
+    fn should_parse_network_conditions() {
+        assert_eq!(
+            Ok(NetworkConditions {
+                latency: Duration::from_millis(100),
+                jitter: Duration::from_millis(30),
+                loss: 0.05,
+                duplication: 0.01,
+                reordering: 0.0,
+            }),
+            "latency=100ms, jitter=30ms,loss=5%,duplicate=1%".parse::<NetworkConditions>()
+        );
+        assert!("latency=100".parse::<NetworkConditions>().is_err());
+        assert!("loss=120%".parse::<NetworkConditions>().is_err());
+        assert!("bandwidth=1%".parse::<NetworkConditions>().is_err());
+    }
+
+    #[tokio::test]
+    async fn should_delay_and_duplicate_lines_keeping_order() {
+        let started_at = Instant::now();
+
+        let received = transfer(
+            "Move 1 Up\nMove 1 Left\n",
+            NetworkConditions {
+                latency: Duration::from_millis(50),
+                jitter: Duration::from_millis(20),
+                duplication: 1.0,
+                ..NetworkConditions::default()
+            },
+        )
+        .await;
+
+        assert!(started_at.elapsed() >= Duration::from_millis(30));
+        assert_eq!(
+            vec!["Move 1 Up", "Move 1 Up", "Move 1 Left", "Move 1 Left"],
+            received
+        );
+    }
+
+    #[tokio::test]
+    async fn should_lose_lines() {
+        let received = transfer(
+            "Move 1 Up\nMove 1 Left\n",
+            NetworkConditions {
+                loss: 1.0,
+                ..NetworkConditions::default()
+            },
+        )
+        .await;
+
+        assert!(received.is_empty());
+    }
+}
@@ -1,329 +1,389 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use log::debug;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::{Point, Rect};
-use sdl2::render::{Texture, WindowCanvas};
-use sdl2::ttf::Font;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::clock::{Clock, SystemClock};
+use crate::geometry::{Point, Rect};
+use crate::level::{BoxAreaLayout, Level};
+use crate::lifecycle::Lifecycle;
+use crate::net::{BoxAreaContent, Command, Direction, Rules};
+use crate::player::Player;
+
+/// Time between two checks of box areas for new boxes or items to be removed
+const BOX_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
 
-use crate::sprite::Sprite;
-use crate::{Player, GLASS_SPACE};
+/// World time simulated with each update by clients, bots and tools, independent of their frame rate
+pub const TIMESTEP: Duration = Duration::from_millis(10);
 
 pub struct World {
     player: Player,
-    _remote_player: Option<Player>,
-    right_top_box_area: BoxArea,
-    right_bottom_box_area: BoxArea,
-    left_bottom_box_area: BoxArea,
-    left_top_box_area: BoxArea,
-    stops: Vec<Point>,
+    remote_players: HashMap<String, Player>,
+    outgoing: Vec<Command>,
+    rules: Rules,
+    lifecycle: Lifecycle,
+    leaderboard: Vec<(String, u32)>,
+    ranking: Option<(usize, u32)>,
+    box_areas: Vec<BoxArea>,
+    level: Level,
+    /// Milliseconds since the Unix epoch the world was created at
+    started_at: i64,
+    /// World time passed by updates since the world was created
+    elapsed: Duration,
+    rng: StdRng,
+    since_box_update: Duration,
+    /// Box areas change over time only as told by the server, if connected
+    following_server: bool,
 }
 
 /// The world, the player and any item exists within
 impl World {
     /// Creates and initializes new playable world.
     pub fn init() -> World {
-        World {
-            player: Player::init(),
-            _remote_player: None,
-            right_top_box_area: BoxArea::new(BoxAreaPosition::RightTop, BoxAreaContent::EmptyGlass),
-            right_bottom_box_area: BoxArea::new(
-                BoxAreaPosition::RightBottom,
-                BoxAreaContent::HiddenBox,
-            ),
-            left_bottom_box_area: BoxArea::new(
-                BoxAreaPosition::LeftBottom,
-                BoxAreaContent::Nothing,
-            ),
-            left_top_box_area: BoxArea::new(BoxAreaPosition::LeftTop, BoxAreaContent::Nothing),
-            stops: vec![
-                Point::new(380, 60),
-                Point::new(590, 450),
-                Point::new(720, 300),
-                Point::new(20, 410),
-                Point::new(190, 560),
-            ],
+        Self::new(SystemClock, rand::random())
+    }
+
+    /// Creates and initializes new playable world starting at the time of given clock,
+    /// using given seed for random numbers.
+    ///
+    /// World time only passes by updates, so given the same seed, start time, updates and commands,
+    /// worlds behave the same.
+    pub fn new(clock: impl Clock, seed: u64) -> World {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut world = World {
+            player: Player::init(&mut rng),
+            remote_players: HashMap::new(),
+            outgoing: vec![],
+            rules: Rules::default(),
+            lifecycle: Lifecycle::from_rules(&Rules::default()),
+            leaderboard: vec![],
+            ranking: None,
+            box_areas: vec![],
+            level: Level::default(),
+            started_at: clock.now_millis(),
+            elapsed: Duration::ZERO,
+            rng,
+            since_box_update: Duration::ZERO,
+            following_server: false,
+        };
+        world.set_level(&Level::default());
+        world
+    }
+
+    /// Uses the layout of given level: its size, stones, decorations, box areas and lounges.
+    ///
+    /// The local player is placed at one of its spawn points.
+    pub fn set_level(&mut self, level: &Level) {
+        self.level = level.clone();
+        self.box_areas = level.box_areas.iter().map(BoxAreaLayout::box_area).collect();
+        self.set_lifecycle(self.lifecycle_of(&self.rules));
+        if let Some(spawn) = level.spawns.choose(&mut self.rng) {
+            self.player.set_position(spawn.x.max(0) as u32, spawn.y.max(0) as u32);
         }
     }
 
-    pub fn get_player(&mut self, _: String) -> &mut Player {
-        &mut self.player
+    /// Returns the level this world is laid out by
+    pub fn level(&self) -> &Level {
+        &self.level
     }
 
-    pub fn playable_rect() -> Rect {
-        Rect::new(0, 50, 800, 550)
+    /// Returns milliseconds since the Unix epoch in world time
+    fn now(&self) -> i64 {
+        self.started_at + self.elapsed.as_millis() as i64
     }
 
-    /// Handles key events for player move.
-    ///
-    /// This checks if player collides with any stop item or will move out of world.
-    /// If player can move, move him and turn him to the correct side.
-    pub fn handle_event(&mut self, event: Event) {
-        let player_id = self.player.id.clone();
-        match event {
-            Event::KeyDown {
-                keycode: Some(Keycode::Up | Keycode::W),
-                ..
-            } => {
-                self.execute_command(Command::MovePlayer(player_id.clone(), Direction::Up));
-                self.if_collides_execute(
-                    vec![
-                        Command::MovePlayer(player_id.clone(), Direction::Down),
-                        Command::FacePlayer(player_id, Direction::Up),
-                    ]
-                )
-            }
-            Event::KeyDown {
-                keycode: Some(Keycode::Down | Keycode::S),
-                ..
-            } => {
-                self.execute_command(Command::MovePlayer(player_id.clone(), Direction::Down));
-                self.if_collides_execute(
-                    vec![
-                        Command::MovePlayer(player_id.clone(), Direction::Up),
-                        Command::FacePlayer(player_id, Direction::Down)
-                    ]
-                )
-            }
-            Event::KeyDown {
-                keycode: Some(Keycode::Left | Keycode::A),
-                ..
-            } => {
-                self.execute_command(Command::MovePlayer(player_id.clone(), Direction::Left));
-                self.if_collides_execute(
-                    vec![
-                        Command::MovePlayer(player_id.clone(), Direction::Right),
-                        Command::FacePlayer(player_id, Direction::Left),
-                    ]
-                )
-            }
-            Event::KeyDown {
-                keycode: Some(Keycode::Right | Keycode::D),
-                ..
-            } => {
-                self.execute_command(Command::MovePlayer(player_id.clone(), Direction::Right));
-                self.if_collides_execute(
-                    vec![
-                        Command::MovePlayer(player_id.clone(), Direction::Left),
-                        Command::FacePlayer(player_id, Direction::Right),
-                    ]
-                )
-            }
-            Event::KeyUp { .. } => self.execute_command(Command::StopPlayer(player_id)),
-            _ => {}
-        }
+    /// Returns the lifecycle of the level or, without, the one given by the rules
+    fn lifecycle_of(&self, rules: &Rules) -> Lifecycle {
+        self.level.lifecycle.clone().unwrap_or_else(|| Lifecycle::from_rules(rules))
+    }
 
-        self.handle_item_collisions();
+    /// Uses given lifecycle for box areas, their timers starting anew with their current content
+    pub fn set_lifecycle(&mut self, lifecycle: Lifecycle) {
+        self.lifecycle = lifecycle;
+        self.restart_box_area_timers();
+    }
+
+    /// Leaves changing box areas over time to the server while connected to one, so all players see
+    /// the same contents. Offline, the timers of the world change them.
+    pub fn follow_server(&mut self, following: bool) {
+        self.following_server = following;
     }
 
-    fn if_collides_execute(&mut self, commands: Vec<Command>) {
-        if self.collides_with_stop() || !self.player.within_rect(&Self::playable_rect()) {
-            commands.into_iter().for_each(|command| {
-                self.execute_command(command);
-            });
+    fn restart_box_area_timers(&mut self) {
+        let now = self.now();
+        for box_area in &mut self.box_areas {
+            box_area.expires_at = self.lifecycle.expires_at(&box_area.content, now, &mut self.rng);
         }
     }
 
-    /// Executes a command for world update.
-    pub fn execute_command(&mut self, command: Command) {
-        debug!("{}", command);
+    pub fn get_player(&mut self, player_id: &str) -> Option<&mut Player> {
+        if self.player.id == player_id {
+            return Some(&mut self.player);
+        }
+        self.remote_players.get_mut(player_id)
+    }
 
-        match command {
-            Command::FacePlayer(player_id, Direction::Down) => {
-                &mut self.get_player(player_id).face_down()
-            },
-            Command::FacePlayer(player_id, Direction::Up) => {
-                &mut self.get_player(player_id).face_up()
-            },
-            Command::FacePlayer(player_id, Direction::Left) => {
-                &mut self.get_player(player_id).face_left()
-            },
-            Command::FacePlayer(player_id, Direction::Right) => {
-                &mut self.get_player(player_id).face_right()
-            },
-            Command::MovePlayer(player_id, Direction::Down) => {
-                &mut self.get_player(player_id).move_down()
-            },
-            Command::MovePlayer(player_id, Direction::Up) => {
-                &mut self.get_player(player_id).move_up()
-            },
-            Command::MovePlayer(player_id, Direction::Left) => {
-                &mut self.get_player(player_id).move_left()
-            },
-            Command::MovePlayer(player_id, Direction::Right) => {
-                &mut self.get_player(player_id).move_right()
-            },
-            Command::StopPlayer(player_id) => {
-                &mut self.get_player(player_id).stop()
-            },
-            Command::UpdateBoxArea(position, content) => &mut {
-                match position {
-                    BoxAreaPosition::RightTop => {
-                        self.right_top_box_area.update_content(content);
-                        self.right_top_box_area.last_update = chrono::Utc::now().timestamp();
-                    }
-                    BoxAreaPosition::RightBottom => {
-                        self.right_bottom_box_area.update_content(content);
-                        self.right_top_box_area.last_update = chrono::Utc::now().timestamp();
-                    }
-                    BoxAreaPosition::LeftBottom => {
-                        self.left_bottom_box_area.update_content(content);
-                        self.right_top_box_area.last_update = chrono::Utc::now().timestamp();
-                    }
-                    BoxAreaPosition::LeftTop => {
-                        self.left_top_box_area.update_content(content);
-                        self.right_top_box_area.last_update = chrono::Utc::now().timestamp();
-                    }
-                };
-            },
-            _ => unimplemented!()
-        };
+    /// Uses the player id assigned by the server for the local player
+    pub fn set_player_id(&mut self, player_id: &str) {
+        self.player.id = player_id.to_string();
     }
 
-    /// Updates box areas to provide new boxes and remove items after some time
-    pub fn update_box_areas(&mut self) {
-        self.update_box_area(BoxAreaPosition::RightTop);
-        self.update_box_area(BoxAreaPosition::RightBottom);
-        self.update_box_area(BoxAreaPosition::LeftBottom);
-        self.update_box_area(BoxAreaPosition::LeftTop);
+    /// Returns the local player
+    pub fn player(&self) -> &Player {
+        &self.player
     }
 
-    /// Handles both, collisions with lounge and any box area
-    pub fn handle_item_collisions(&mut self) {
-        self.handle_lounge_collisions();
-        self.handle_boxarea_collisions();
+    /// Returns all players except the local one
+    pub fn remote_players(&self) -> impl Iterator<Item = &Player> {
+        self.remote_players.values()
     }
 
-    /// Renders world using given canvas, texture and font
-    pub fn render(&self, canvas: &mut WindowCanvas, texture: &Texture, font: &Font) {
-        canvas.clear();
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
 
-        canvas.set_draw_color(Color::RGB(160, 90, 44));
-        let _r = canvas.fill_rect(Rect::new(0, 0, 800, 45));
+    /// Returns names and points of the best players, as sent by the server
+    pub fn leaderboard(&self) -> &[(String, u32)] {
+        &self.leaderboard
+    }
 
-        canvas.set_draw_color(Color::RGB(206, 182, 115));
+    /// Returns rank and points of the best game of the local player, if known
+    pub fn ranking(&self) -> Option<(usize, u32)> {
+        self.ranking
+    }
 
-        // Points/Glasses
-        (1..=GLASS_SPACE).for_each(|i| {
-            canvas.set_draw_color(Color::RGB(128, 51, 0));
-            let _r = canvas.fill_rect(Rect::new(5, 37, GLASS_SPACE as u32 * 25 + 5, 4));
+    pub fn box_areas(&self) -> &[BoxArea] {
+        &self.box_areas
+    }
 
-            if self.player.filled_glasses + self.player.empty_glasses >= i {
-                Sprite::GlassEmpty.render(canvas, texture, (i as i32) * 25 - 15, 10);
-            }
-            if self.player.filled_glasses >= i {
-                Sprite::GlassFilled.render(canvas, texture, (i as i32) * 25 - 15, 10);
-            }
-        });
+    /// Adds a box area, replacing any box area with the same id
+    pub fn add_box_area(&mut self, mut box_area: BoxArea) {
+        box_area.expires_at = self.lifecycle.expires_at(&box_area.content, self.now(), &mut self.rng);
+        self.box_areas.retain(|other| other.id != box_area.id);
+        self.box_areas.push(box_area);
+    }
+
+    /// Returns positions of stones the player cannot pass
+    pub fn stops(&self) -> &[Point] {
+        &self.level.stones
+    }
+
+    /// Returns positions of flowers decorating the world
+    pub fn flowers(&self) -> &[Point] {
+        &self.level.flowers
+    }
+
+    /// Takes commands executed for the local player and world to be sent to the server
+    pub fn take_outgoing(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    pub fn playable_rect(&self) -> Rect {
+        self.level.playable_rect()
+    }
+
+    /// Returns the lounges, players drink their glasses of wine in
+    pub fn lounge_rects(&self) -> Vec<Rect> {
+        self.level.lounge_rects()
+    }
 
-        // Lounge
-        Sprite::Lounge.render(canvas, texture, 325, 260);
-
-        // Box Areas
-        self.right_top_box_area.render(canvas, texture);
-        self.right_bottom_box_area.render(canvas, texture);
-        self.left_bottom_box_area.render(canvas, texture);
-        self.left_top_box_area.render(canvas, texture);
-
-        // Decoration
-        Sprite::Flower.render(canvas, texture, 235, 130);
-        Sprite::Flower.render(canvas, texture, 120, 210);
-        Sprite::Flower.render(canvas, texture, 535, 150);
-        Sprite::Flower.render(canvas, texture, 435, 370);
-        Sprite::Flower.render(canvas, texture, 235, 470);
-        Sprite::Flower.render(canvas, texture, 555, 510);
-
-        // Stops
-        for s in &self.stops {
-            Sprite::Stone.render(canvas, texture, s.x(), s.y())
+    /// Starts moving the local player into given direction, like pressing a key does
+    pub fn start_moving(&mut self, direction: Direction) {
+        if !self.player.moving().contains(&direction) {
+            self.execute_local_command(Command::MovePlayer(self.player.id.clone(), direction));
         }
+    }
 
-        // Player
-        self.player.render(canvas, texture);
-
-        // Points
-        let x = font
-            .render(format!("Score: {:#04}", self.player.points).as_str())
-            .blended(Color::RGBA(246, 222, 155, 255))
-            .unwrap();
-        let t2 = canvas.texture_creator();
-        let t2 = t2.create_texture_from_surface(&x).unwrap();
-
-        let _r = canvas.copy(
-            &t2,
-            x.rect(),
-            Some(Rect::new(790 - x.width() as i32, 16, x.width(), x.height())),
-        );
-        canvas.set_draw_color(Color::RGB(206, 182, 115));
+    /// Stops moving the local player into given direction, like releasing a key does.
+    ///
+    /// Other players are told to stop the player and it starts moving again into directions still held.
+    pub fn stop_moving(&mut self, direction: &Direction) {
+        if !self.player.moving().contains(direction) {
+            return;
+        }
+        let remaining: Vec<Direction> = self.player.moving().iter().filter(|d| *d != direction).cloned().collect();
+        self.stop_player();
+        remaining.into_iter().for_each(|direction| self.start_moving(direction));
+    }
+
+    /// Stops the local player moving into any direction at its current position
+    pub fn stop_player(&mut self) {
+        if self.player.moving().is_empty() {
+            return;
+        }
+        let position = self.player.position();
+        self.execute_local_command(Command::StopPlayer(
+            self.player.id.clone(),
+            position.x.max(0) as u32,
+            position.y.max(0) as u32,
+        ));
+    }
+
+    /// Handles collisions with items and sends the inventory of the local player if changed
+    fn handle_inventory_changes(&mut self) {
+        let inventory = (self.player.empty_glasses, self.player.filled_glasses, self.player.points);
+        self.handle_item_collisions();
+        if inventory != (self.player.empty_glasses, self.player.filled_glasses, self.player.points) {
+            self.execute_local_command(Command::UpdateInventory(
+                self.player.id.clone(),
+                self.player.empty_glasses,
+                self.player.filled_glasses,
+                self.player.points,
+            ));
+        }
+    }
 
-        canvas.present();
+    /// Checks if the local player could stand at given position, within the world and off any stop
+    pub fn can_stand_at(&self, position: Point) -> bool {
+        self.level.can_stand(&self.player.at(position))
     }
 
-    fn update_box_area(&mut self, box_area_position: BoxAreaPosition) {
-        let box_area = match box_area_position {
-            BoxAreaPosition::RightTop => &self.right_top_box_area,
-            BoxAreaPosition::RightBottom => &self.right_bottom_box_area,
-            BoxAreaPosition::LeftBottom => &self.left_bottom_box_area,
-            BoxAreaPosition::LeftTop => &self.left_top_box_area,
+    /// Executes a command for world update.
+    pub fn execute_command(&mut self, command: Command) {
+        debug!("{}", command);
+
+        match command {
+            Command::SpawnPlayer(player_id, x, y) if player_id == self.player.id => {
+                self.player.set_position(x, y)
+            }
+            Command::SpawnPlayer(player_id, x, y) => {
+                let player = Player::spawn(&player_id, x, y);
+                self.remote_players.insert(player_id, player);
+            }
+            Command::RemovePlayer(player_id) => {
+                self.remote_players.remove(&player_id);
+            }
+            Command::FacePlayer(player_id, direction) => {
+                if let Some(player) = self.get_player(&player_id) {
+                    match direction {
+                        Direction::Down => player.face_down(),
+                        Direction::Up => player.face_up(),
+                        Direction::Left => player.face_left(),
+                        Direction::Right => player.face_right(),
+                    }
+                }
+            }
+            Command::MovePlayer(player_id, direction) => {
+                if let Some(player) = self.get_player(&player_id) {
+                    player.start_moving(direction)
+                }
+            }
+            Command::StopPlayer(player_id, x, y) => {
+                if let Some(player) = self.get_player(&player_id) {
+                    player.stop();
+                    player.set_position(x, y);
+                }
+            }
+            Command::UpdateInventory(player_id, empty_glasses, filled_glasses, points) => {
+                if let Some(player) = self.get_player(&player_id) {
+                    player.empty_glasses = empty_glasses;
+                    player.filled_glasses = filled_glasses;
+                    player.points = points;
+                }
+            }
+            Command::UpdateLeaderboard(scores) => self.leaderboard = scores,
+            Command::UpdateRanking(_, rank, points) => {
+                self.ranking = if rank > 0 { Some((rank, points)) } else { None }
+            }
+            Command::UpdateRules(rules) => {
+                self.set_lifecycle(self.lifecycle_of(&rules));
+                self.rules = rules;
+            }
+            Command::UpdateBoxArea(id, content) => {
+                let expires_at = self.lifecycle.expires_at(&content, self.now(), &mut self.rng);
+                if let Some(box_area) = self.box_areas.iter_mut().find(|box_area| box_area.id == id) {
+                    box_area.update_content(content, expires_at);
+                }
+            }
         };
+    }
 
-        let now = chrono::Utc::now().timestamp();
-        let r: i64 = (rand::random::<i64>() % 10) + 3;
+    /// Executes a command caused by the local player and keeps it to be sent to the server.
+    fn execute_local_command(&mut self, command: Command) {
+        self.outgoing.push(command.clone());
+        self.execute_command(command);
+    }
 
-        if box_area.content == BoxAreaContent::Nothing && box_area.last_update + 10 < now {
-            self.execute_command(Command::UpdateBoxArea(
-                box_area_position,
-                BoxAreaContent::HiddenBox,
-            ));
-        } else if box_area.content != BoxAreaContent::Nothing && box_area.last_update + 30 < now - r
-        {
-            self.execute_command(Command::UpdateBoxArea(
-                box_area_position,
-                BoxAreaContent::Nothing,
-            ));
+    /// Advances the world by given time and handles everything depending on time passing.
+    ///
+    /// Moving players walk the distance of the given time and the local player handles collisions
+    /// with items afterwards. Unless following the server, box areas are checked once per second of world time,
+    /// however the time is split up, and their timers expire by world time only.
+    pub fn update(&mut self, dt: Duration) {
+        self.elapsed += dt;
+        let position = self.player.position();
+        self.move_players(dt);
+        if self.player.position() != position {
+            self.handle_inventory_changes();
+        }
+
+        if self.following_server {
+            return;
+        }
+        self.since_box_update += dt;
+        while self.since_box_update >= BOX_UPDATE_INTERVAL {
+            self.since_box_update -= BOX_UPDATE_INTERVAL;
+            self.update_box_areas();
+        }
+    }
+
+    /// Moves all moving players by the distance walked within given time, as the level allows.
+    fn move_players(&mut self, dt: Duration) {
+        let level = &self.level;
+        for player in std::iter::once(&mut self.player).chain(self.remote_players.values_mut()) {
+            if let Some(moved) = level.walk(player, dt) {
+                *player = moved;
+            }
         }
     }
 
+    /// Changes the content of box areas whose timer expired, as given by the lifecycle
+    fn update_box_areas(&mut self) {
+        let now = self.now();
+        let changes: Vec<(String, BoxAreaContent)> = self
+            .box_areas
+            .iter()
+            .filter(|box_area| box_area.expires_at.is_some_and(|expires_at| expires_at <= now))
+            .filter_map(|box_area| {
+                let transition = self.lifecycle.transition(&box_area.content)?;
+                Some((box_area.id.clone(), transition.to.clone()))
+            })
+            .collect();
+        for (id, content) in changes {
+            self.execute_local_command(Command::UpdateBoxArea(id, content));
+        }
+    }
+
+    /// Handles both, collisions with lounge and any box area
+    pub fn handle_item_collisions(&mut self) {
+        self.handle_lounge_collisions();
+        self.handle_boxarea_collisions();
+    }
+
     fn has_player_collision(&mut self) -> Collision {
-        if let Some(ba) = self.collides_with_box_area() {
-            return Collision::BoxArea(ba);
+        if let Some(id) = self.collides_with_box_area() {
+            return Collision::BoxArea(id);
         } else if self.collides_with_lounge() {
             return Collision::Lounge;
-        } else if self.collides_with_stop() {
+        } else if self.level.collides_with_stone(&self.player) {
             return Collision::Stopper;
         }
 
         Collision::None
     }
 
-    fn collides_with_box_area(&mut self) -> Option<BoxAreaPosition> {
-        if self.right_top_box_area.collides_with(&self.player) {
-            return Some(BoxAreaPosition::RightTop);
-        } else if self.right_bottom_box_area.collides_with(&self.player) {
-            return Some(BoxAreaPosition::RightBottom);
-        } else if self.left_bottom_box_area.collides_with(&self.player) {
-            return Some(BoxAreaPosition::LeftBottom);
-        } else if self.left_top_box_area.collides_with(&self.player) {
-            return Some(BoxAreaPosition::LeftTop);
-        }
-
-        None
+    fn collides_with_box_area(&mut self) -> Option<String> {
+        self.box_areas
+            .iter()
+            .find(|box_area| box_area.collides_with(&self.player))
+            .map(|box_area| box_area.id.clone())
     }
 
     fn collides_with_lounge(&mut self) -> bool {
-        let lounge_rect = Rect::new(325, 260, 150, 95);
-        lounge_rect.contains_point(self.player.center())
-    }
-
-    fn collides_with_stop(&mut self) -> bool {
-        for s in &self.stops {
-            let x = s.x() + 12;
-            let y = s.y() + 12;
-            if self.player.bounding_rect().contains_point(Point::new(x, y)) {
-                return true;
-            }
-        }
-        false
+        self.lounge_rects().iter().any(|lounge| lounge.contains_point(self.player.center()))
     }
 
     fn handle_lounge_collisions(&mut self) {
@@ -332,169 +392,325 @@ impl World {
         }
     }
 
-    // TODO Commands
+    /// Takes from the box area the local player is in, telling the server which one
     fn handle_boxarea_collisions(&mut self) {
-        if let Collision::BoxArea(bap) = self.has_player_collision() {
-            let ba = match bap {
-                BoxAreaPosition::RightTop => &mut self.right_top_box_area,
-                BoxAreaPosition::RightBottom => &mut self.right_bottom_box_area,
-                BoxAreaPosition::LeftBottom => &mut self.left_bottom_box_area,
-                BoxAreaPosition::LeftTop => &mut self.left_top_box_area,
-            };
-
-            let content = match &ba.content {
-                BoxAreaContent::HiddenBox => BoxAreaContent::random(),
-                BoxAreaContent::EmptyGlass => BoxAreaContent::EmptyGlass,
-                BoxAreaContent::FilledBottle => BoxAreaContent::FilledBottle,
-                _ => BoxAreaContent::Nothing,
+        if let Collision::BoxArea(id) = self.has_player_collision() {
+            let content = match self.box_areas.iter().find(|box_area| box_area.id == id) {
+                Some(box_area) => box_area.content.clone(),
+                None => return,
             };
-
-            if content == BoxAreaContent::EmptyGlass && self.player.can_pick_glass() {
-                self.execute_command(Command::UpdateBoxArea(bap, BoxAreaContent::Nothing));
-                self.player.pick_glass();
-            } else if content == BoxAreaContent::EmptyGlass && !self.player.can_pick_glass() {
-                self.execute_command(Command::UpdateBoxArea(bap, BoxAreaContent::EmptyGlass));
-            } else if content == BoxAreaContent::FilledBottle && self.player.can_fill_glass() {
-                self.execute_command(Command::UpdateBoxArea(bap, BoxAreaContent::EmptyBottle));
-                self.player.fill_glass();
-            } else if content == BoxAreaContent::FilledBottle && !self.player.can_fill_glass() {
-                self.execute_command(Command::UpdateBoxArea(bap, BoxAreaContent::FilledBottle));
+            if let Some(content) = self.player.take_from(&content, self.rules.glass_space, &mut self.rng) {
+                self.execute_local_command(Command::UpdateBoxArea(id, content));
             }
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
-
-#[derive(Debug, PartialEq)]
-pub enum Command {
-    SpawnPlayer(String, u32, u32),
-    RemovePlayer(String),
-    FacePlayer(String, Direction),
-    MovePlayer(String, Direction),
-    StopPlayer(String),
-    UpdateBoxArea(BoxAreaPosition, BoxAreaContent),
-}
-
 #[derive(Debug, PartialEq, Eq)]
 enum Collision {
-    BoxArea(BoxAreaPosition),
+    BoxArea(String),
     Lounge,
     Stopper,
     None,
 }
 
+/// Area with a box that may contain items, surrounded by bushes except for its opening
 #[derive(Debug)]
-struct BoxArea {
-    position: BoxAreaPosition,
+pub struct BoxArea {
+    id: String,
+    rect: Rect,
+    opening: Direction,
     pub content: BoxAreaContent,
-    last_update: i64,
+    /// Time in milliseconds the content changes on its own, if ever
+    expires_at: Option<i64>,
 }
 
 impl BoxArea {
-    /// Creates a new BoxArea
-    fn new(position: BoxAreaPosition, content: BoxAreaContent) -> BoxArea {
+    /// Creates a new BoxArea, its content not changing until added to a world
+    pub fn new(id: &str, rect: Rect, opening: Direction, content: BoxAreaContent) -> BoxArea {
         BoxArea {
-            position,
+            id: id.to_string(),
+            rect,
+            opening,
             content,
-            last_update: chrono::Utc::now().timestamp(),
+            expires_at: None,
         }
     }
 
-    fn update_content(&mut self, content: BoxAreaContent) {
+    fn update_content(&mut self, content: BoxAreaContent, expires_at: Option<i64>) {
         self.content = content;
-        self.last_update = chrono::Utc::now().timestamp();
+        self.expires_at = expires_at;
     }
 
-    fn bounding_rect(&self) -> Rect {
-        let x_offset = match self.position {
-            BoxAreaPosition::RightTop => 685,
-            BoxAreaPosition::RightBottom => 685,
-            BoxAreaPosition::LeftBottom => 5,
-            BoxAreaPosition::LeftTop => 5,
-        };
-        let y_offset = match self.position {
-            BoxAreaPosition::RightTop => 50,
-            BoxAreaPosition::RightBottom => 480,
-            BoxAreaPosition::LeftBottom => 480,
-            BoxAreaPosition::LeftTop => 50,
-        };
+    /// Returns the id, box areas are addressed by in commands
+    pub fn id(&self) -> &str {
+        &self.id
+    }
 
-        Rect::new(x_offset, y_offset, 110, 110)
+    /// Returns the side of the box area without bushes
+    pub fn opening(&self) -> &Direction {
+        &self.opening
+    }
+
+    pub fn bounding_rect(&self) -> Rect {
+        self.rect
     }
 
     /// Checks if player collides with this BoxArea
     fn collides_with(&self, player: &Player) -> bool {
         self.bounding_rect().contains_point(player.center())
     }
+}
 
-    /// Renders BoxArea using goven Canvas and Texture
-    fn render(&self, canvas: &mut WindowCanvas, texture: &Texture) {
-        let x_offset = self.bounding_rect().x();
-        let y_offset = self.bounding_rect().y();
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
 
-        // Border
-        Sprite::BushHorizontal.render(canvas, texture, x_offset + 30, y_offset);
-        Sprite::BushHorizontal.render(canvas, texture, x_offset + 30, y_offset + 85);
-        let (dst_x, dst_y) = match self.position {
-            BoxAreaPosition::RightTop => (x_offset + 85, y_offset + 30),
-            BoxAreaPosition::RightBottom => (x_offset + 85, y_offset + 30),
-            BoxAreaPosition::LeftBottom => (x_offset, y_offset + 30),
-            BoxAreaPosition::LeftTop => (x_offset, y_offset + 30),
-        };
-        Sprite::BushVertical.render(canvas, texture, dst_x, dst_y);
-
-        // Box
-        let box_sprite = match &self.content {
-            BoxAreaContent::HiddenBox => Sprite::HiddenBox,
-            BoxAreaContent::FilledBottle => Sprite::BottleFilled,
-            BoxAreaContent::EmptyBottle => Sprite::BottleEmpty,
-            BoxAreaContent::EmptyGlass => Sprite::GlassEmpty,
-            BoxAreaContent::Nothing => Sprite::Nothing,
+    use crate::clock::ManualClock;
+    use crate::geometry::{Point, Rect};
+    use crate::level::Level;
+    use crate::lifecycle::{Lifecycle, Transition};
+    use crate::net::{BoxAreaContent, Command, Direction, Rules};
+    use crate::world::{BoxArea, World};
+
+    /// Opens the hidden box and waits for box areas to be refilled and cleared
+    fn play(seed: u64) -> (String, Vec<Command>) {
+        let clock = ManualClock::starting_at(1_700_000_000_000);
+        let mut world = World::new(clock, seed);
+        let player_id = world.player().id.clone();
+
+        world.update_box_areas();
+        assert!(world.take_outgoing().is_empty());
+
+        world.execute_command(Command::SpawnPlayer(player_id.clone(), 720, 470));
+        world.handle_inventory_changes();
+        world.update(Duration::from_secs(60));
+
+        (player_id, world.take_outgoing())
+    }
+
+    #[test]
+    fn should_update_box_areas_once_per_second_of_world_time() {
+        let clock = ManualClock::starting_at(1_700_000_000_000);
+        let mut world = World::new(clock, 42);
+
+        world.update(Duration::from_millis(9_600));
+        assert!(world.take_outgoing().is_empty());
+
+        world.update(Duration::from_millis(600));
+        let commands = world.take_outgoing();
+        assert!(commands.contains(&Command::UpdateBoxArea(
+            "LeftTop".to_string(),
+            BoxAreaContent::HiddenBox
+        )));
+
+        world.update(Duration::from_millis(600));
+        assert!(world.take_outgoing().is_empty());
+    }
+
+    #[test]
+    fn should_leave_box_areas_to_server_when_following() {
+        let clock = ManualClock::starting_at(1_700_000_000_000);
+        let mut world = World::new(clock, 42);
+        world.follow_server(true);
+
+        world.update(Duration::from_secs(60));
+        assert!(world.take_outgoing().is_empty());
+
+        world.execute_command(Command::UpdateBoxArea("LeftTop".to_string(), BoxAreaContent::HiddenBox));
+        assert_eq!(BoxAreaContent::HiddenBox, world.box_areas()[3].content);
+    }
+
+    #[test]
+    fn should_run_independent_timer_per_box_area() {
+        let clock = ManualClock::starting_at(1_700_000_000_000);
+        let mut world = World::new(clock, 42);
+        world.update(Duration::from_secs(5));
+        world.execute_command(Command::UpdateBoxArea(
+            "LeftBottom".to_string(),
+            BoxAreaContent::Nothing,
+        ));
+
+        world.update(Duration::from_secs(6));
+        assert_eq!(
+            vec![Command::UpdateBoxArea(
+                "LeftTop".to_string(),
+                BoxAreaContent::HiddenBox
+            )],
+            world.take_outgoing()
+        );
+
+        world.update(Duration::from_secs(3));
+        assert!(world.take_outgoing().is_empty());
+
+        world.update(Duration::from_secs(1));
+        assert_eq!(
+            vec![Command::UpdateBoxArea(
+                "LeftBottom".to_string(),
+                BoxAreaContent::HiddenBox
+            )],
+            world.take_outgoing()
+        );
+    }
+
+    #[test]
+    fn should_follow_transitions_of_lifecycle() {
+        let clock = ManualClock::starting_at(1_700_000_000_000);
+        let mut world = World::new(clock, 42);
+        let transition = |from, to, after| Transition {
+            from,
+            to,
+            after: Duration::from_secs(after),
+            jitter: Duration::ZERO,
         };
-        let (box_width, box_height) = box_sprite.size();
-        box_sprite.render(
-            canvas,
-            texture,
-            x_offset + 30 + (50 - box_width as i32) / 2,
-            y_offset + 30 + (50 - box_height as i32) / 2,
+        world.set_lifecycle(Lifecycle::new(vec![
+            transition(BoxAreaContent::Nothing, BoxAreaContent::HiddenBox, 2),
+            transition(BoxAreaContent::HiddenBox, BoxAreaContent::EmptyGlass, 3),
+        ]));
+
+        let mut contents = vec![];
+        for _ in 0..6 {
+            world.update(Duration::from_secs(1));
+            contents.push(world.box_areas()[3].content.clone());
+        }
+
+        assert_eq!(
+            vec![
+                BoxAreaContent::Nothing,
+                BoxAreaContent::HiddenBox,
+                BoxAreaContent::HiddenBox,
+                BoxAreaContent::HiddenBox,
+                BoxAreaContent::EmptyGlass,
+                BoxAreaContent::EmptyGlass,
+            ],
+            contents
         );
+        // Without a transition leaving it, the glass stays until picked
+        world.update(Duration::from_secs(60));
+        assert_eq!(BoxAreaContent::EmptyGlass, world.box_areas()[3].content);
     }
-}
 
-/// Position of a BoxArea.
-/// There are only four possible values for each vertex of the world.
-#[derive(Debug, PartialEq, Eq)]
-pub enum BoxAreaPosition {
-    RightTop,
-    RightBottom,
-    LeftBottom,
-    LeftTop,
-}
+    #[test]
+    fn should_keep_lifecycle_of_level_when_rules_change() {
+        let clock = ManualClock::starting_at(1_700_000_000_000);
+        let mut world = World::new(clock, 42);
+        world.set_level(&Level {
+            lifecycle: Some(Lifecycle::new(vec![Transition {
+                from: BoxAreaContent::Nothing,
+                to: BoxAreaContent::FilledBottle,
+                after: Duration::from_secs(2),
+                jitter: Duration::ZERO,
+            }])),
+            ..Level::default()
+        });
+        world.execute_command(Command::UpdateRules(Rules {
+            glass_space: 5,
+            box_refill_after: 60,
+            box_clear_after: 60,
+        }));
+
+        world.update(Duration::from_secs(2));
+        assert_eq!(BoxAreaContent::FilledBottle, world.box_areas()[3].content);
+    }
 
-/// Content of a BoxArea
-#[derive(Debug, PartialEq, Eq)]
-pub enum BoxAreaContent {
-    Nothing,
-    HiddenBox,
-    EmptyGlass,
-    FilledBottle,
-    EmptyBottle,
-}
+    #[test]
+    fn should_address_box_areas_by_id() {
+        let mut world = World::init();
+        world.add_box_area(BoxArea::new(
+            "cellar",
+            Rect::new(400, 420, 60, 60),
+            Direction::Up,
+            BoxAreaContent::EmptyGlass,
+        ));
+        world.execute_command(Command::UpdateBoxArea("attic".to_string(), BoxAreaContent::EmptyGlass));
+        assert_eq!(5, world.box_areas().len());
+
+        let player_id = world.player().id.clone();
+        world.execute_command(Command::SpawnPlayer(player_id, 410, 393));
+        world.handle_inventory_changes();
+
+        assert_eq!(
+            Some(&Command::UpdateBoxArea("cellar".to_string(), BoxAreaContent::Nothing)),
+            world.take_outgoing().first()
+        );
+        assert_eq!(1, world.player().empty_glasses);
+    }
 
-impl BoxAreaContent {
-    /// Selects new random BoxAreaContent
-    fn random() -> BoxAreaContent {
-        match rand::random::<i32>() % 5 {
-            1 | 4 => BoxAreaContent::EmptyGlass,
-            2 | 3 => BoxAreaContent::FilledBottle,
-            _ => BoxAreaContent::Nothing,
-        }
+    #[test]
+    fn should_behave_the_same_given_seed_and_clock() {
+        let (player_id, commands) = play(42);
+
+        assert_eq!((player_id.clone(), commands.clone()), play(42));
+        assert_ne!(player_id, play(43).0);
+        assert!(commands.contains(&Command::UpdateBoxArea(
+            "LeftTop".to_string(),
+            BoxAreaContent::HiddenBox
+        )));
+        assert!(commands.contains(&Command::UpdateBoxArea(
+            "RightTop".to_string(),
+            BoxAreaContent::Nothing
+        )));
+    }
+
+    #[test]
+    fn should_move_diagonally_while_two_directions_held() {
+        let mut world = World::init();
+        world.set_player_id("1234");
+        world.execute_command(Command::SpawnPlayer("1234".to_string(), 380, 250));
+
+        world.start_moving(Direction::Up);
+        world.start_moving(Direction::Right);
+        world.start_moving(Direction::Right);
+        (0..10).for_each(|_| world.update(Duration::from_millis(10)));
+        assert_eq!(Point::new(394, 236), world.player().position());
+
+        world.stop_moving(&Direction::Up);
+        (0..10).for_each(|_| world.update(Duration::from_millis(10)));
+        assert_eq!(Point::new(414, 236), world.player().position());
+
+        world.stop_player();
+        world.update(Duration::from_millis(100));
+        assert_eq!(Point::new(414, 236), world.player().position());
+        assert_eq!(
+            vec![
+                Command::MovePlayer("1234".to_string(), Direction::Up),
+                Command::MovePlayer("1234".to_string(), Direction::Right),
+                Command::StopPlayer("1234".to_string(), 394, 236),
+                Command::MovePlayer("1234".to_string(), Direction::Right),
+                Command::StopPlayer("1234".to_string(), 414, 236),
+            ],
+            world.take_outgoing()
+        );
+    }
+
+    #[test]
+    fn should_move_remote_players_until_stopped() {
+        let mut world = World::init();
+        world.execute_command(Command::SpawnPlayer("5678".to_string(), 100, 300));
+
+        world.execute_command(Command::MovePlayer("5678".to_string(), Direction::Down));
+        world.update(Duration::from_millis(100));
+        assert_eq!(Point::new(100, 320), world.remote_players().next().unwrap().position());
+
+        world.execute_command(Command::StopPlayer("5678".to_string(), 100, 318));
+        world.update(Duration::from_millis(100));
+        assert_eq!(Point::new(100, 318), world.remote_players().next().unwrap().position());
+    }
+
+    #[test]
+    fn should_stop_player_in_front_of_stone() {
+        let mut world = World::init();
+        world.set_player_id("1234");
+        world.execute_command(Command::SpawnPlayer("1234".to_string(), 380, 250));
+
+        world.start_moving(Direction::Up);
+        (0..200).for_each(|_| world.update(Duration::from_millis(10)));
+
+        let position = world.player().position();
+        assert_eq!(380, position.x);
+        assert!((72..=75).contains(&position.y));
+        assert!(world.can_stand_at(Point::new(380, 85)));
+        assert!(!world.can_stand_at(Point::new(380, 70)));
+        assert!(!world.can_stand_at(Point::new(380, 40)));
     }
 }